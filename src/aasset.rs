@@ -1,31 +1,67 @@
-use crate::ResourceLocation;
-use crate::config::{is_no_hurt_cam_enabled, is_no_fog_enabled, is_java_cubemap_enabled, is_particles_disabler_enabled, is_java_clouds_enabled, is_classic_skins_enabled, is_no_shadows_enabled, is_night_vision_enabled, is_xelo_title_enabled, is_client_capes_enabled, is_block_whiteoutline_enabled, is_no_flipbook_animations_enabled};
+use crate::{camera, game_version, hash, material_cache::{self, MappedFile}, material_patch, materials, shader_platforms, status};
+use crate::config::{get_config, RedirectFallback, is_no_fog_enabled, is_java_cubemap_enabled, is_particles_disabler_enabled, is_java_clouds_enabled, is_classic_skins_enabled, is_no_shadows_enabled, is_night_vision_enabled, is_xelo_title_enabled, is_client_capes_enabled, is_block_whiteoutline_enabled, is_no_flipbook_animations_enabled};
 use libc::{off64_t, off_t};
 use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
-use ndk::asset::Asset;
 use ndk_sys::{AAsset, AAssetManager};
 use once_cell::sync::Lazy;
 use scroll::Pread;
 use serde_json::{Value, Map};
 use std::{
     borrow::Cow,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::{CStr, CString, OsStr},
     io::{self, Cursor, Read, Seek, Write},
     os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
-    sync::{Mutex, OnceLock},
+    sync::{Arc, Mutex},
 };
 
 #[derive(PartialEq, Eq, Hash)]
 struct AAssetPtr(*const ndk_sys::AAsset);
 unsafe impl Send for AAssetPtr {}
 
-static MC_VERSION: OnceLock<Option<MinecraftVersion>> = OnceLock::new();
+// Built in materials converted to the running version, or why they can't
+// be. The game version never changes within a process so the name is enough
+// of a key.
+static CONVERTED_BUILTINS: Lazy<Mutex<HashMap<&'static str, CheckedMaterial>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
 
-static WANTED_ASSETS: Lazy<Mutex<HashMap<AAssetPtr, Cursor<Vec<u8>>>>> =
+static WANTED_ASSETS: Lazy<Mutex<HashMap<AAssetPtr, Cursor<AssetData>>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
+// Vanilla materials, by name and content hash, that none of their patches
+// changed, so they aren't parsed again on every open
+static UNPATCHED_MATERIALS: Lazy<Mutex<HashSet<(String, u64)>>> =
+    Lazy::new(|| Mutex::new(HashSet::new()));
+
+// Contents of a replaced asset
+#[derive(Clone)]
+enum AssetData {
+    Owned(Vec<u8>),
+    Static(&'static [u8]),
+    // Straight from the material cache
+    Mapped(Arc<MappedFile>),
+}
+
+impl AsRef<[u8]> for AssetData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            AssetData::Owned(data) => data,
+            AssetData::Static(data) => data,
+            AssetData::Mapped(file) => (**file).as_ref(),
+        }
+    }
+}
+
+// A material that parses as the running version, or why it doesn't
+type CheckedMaterial = Result<AssetData, String>;
+
+impl From<Vec<u8>> for AssetData {
+    fn from(data: Vec<u8>) -> Self {
+        AssetData::Owned(data)
+    }
+}
+
 const LEGACY_CUBEMAP_MATERIAL_BIN: &[u8] = include_bytes!("qol/java_cubemap/LegacyCubemap.material.bin");
 const RENDER_CHUNK_MATERIAL_BIN: &[u8] = include_bytes!("utils/no_fog_materials/RenderChunk.material.bin");
 
@@ -41,10 +77,6 @@ const COMMON_JSON: &[u8] = include_bytes!("optimizers/noparticles/common.json");
 
 const CUSTOM_SPLASHES_JSON: &str = r#"{"splashes":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"]}"#;
 
-const CUSTOM_FIRST_PERSON_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:first_person"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_first_person":{},"minecraft:camera_render_first_person_objects":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,0,0]},"minecraft:camera_direct_look":{"pitch_min":-89.9,"pitch_max":89.9},"minecraft:camera_perspective_option":{"view_mode":"first_person"},"minecraft:update_player_from_camera":{"look_mode":"along_camera"},"minecraft:extend_player_rendering":{},"minecraft:camera_player_sleep_vignette":{},"minecraft:vr_comfort_move":{},"minecraft:default_input_camera":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{}}}}"#;
-const CUSTOM_THIRD_PERSON_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:third_person"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_third_person":{},"minecraft:camera_render_player_model":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,2,5]},"minecraft:camera_look_at_player":{},"minecraft:camera_orbit":{"azimuth_smoothing_spring":0,"polar_angle_smoothing_spring":0,"distance_smoothing_spring":0,"polar_angle_min":0.1,"polar_angle_max":179.9,"radius":4},"minecraft:camera_avoidance":{"relax_distance_smoothing_spring":0,"distance_constraint_min":0.25},"minecraft:camera_perspective_option":{"view_mode":"third_person"},"minecraft:update_player_from_camera":{"look_mode":"along_camera"},"minecraft:camera_player_sleep_vignette":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{},"minecraft:extend_player_rendering":{}}}}"#;
-const CUSTOM_THIRD_PERSON_FRONT_JSON: &str = r#"{"format_version":"1.18.10","minecraft:camera_entity":{"description":{"identifier":"minecraft:third_person_front"},"components":{"minecraft:camera":{"field_of_view":66,"near_clipping_plane":0.025,"far_clipping_plane":2500},"minecraft:camera_third_person":{},"minecraft:camera_render_player_model":{},"minecraft:camera_attach_to_player":{},"minecraft:camera_offset":{"view":[0,0],"entity":[0,2,5]},"minecraft:camera_look_at_player":{},"minecraft:camera_orbit":{"azimuth_smoothing_spring":0,"polar_angle_smoothing_spring":0,"distance_smoothing_spring":0,"polar_angle_min":0.1,"polar_angle_max":179.9,"radius":4,"invert_x_input":true},"minecraft:camera_avoidance":{"relax_distance_smoothing_spring":0,"distance_constraint_min":0.25},"minecraft:camera_perspective_option":{"view_mode":"third_person_front"},"minecraft:update_player_from_camera":{"look_mode":"at_camera"},"minecraft:camera_player_sleep_vignette":{},"minecraft:gameplay_affects_fov":{},"minecraft:allow_inside_block":{},"minecraft:extend_player_rendering":{}}}}"#;
-
 const CUSTOM_LOADING_MESSAGES_JSON: &str = r#"{"beginner_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"mid_game_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"late_game_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"creative_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"editor_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"realms_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"addons_loading_messages":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"],"store_progress_tooltips":["Xelo Client","Xelo > any other client","The Best Client!!","BlueCat","Xelo is so much better","Xelo Optimizes like no other client","Make Sure to star our repository: https://github.com/Xelo-Client/Xelo","Contributions open!","Made by the community, for the community","Yami is goated!!"]}"#;
 
 const CUSTOM_SKINS_JSON: &str = r#"{"skins":[{"localization_name":"Steve","geometry":"geometry.humanoid.custom","texture":"steve.png","type":"free"},{"localization_name":"Alex","geometry":"geometry.humanoid.customSlim","texture":"alex.png","type":"free"}],"serialize_name":"Standard","localization_name":"Standard"}"#;
@@ -77,52 +109,6 @@ const CLASSIC_ALEX_TEXTURE: &[u8] = include_bytes!("a.png");
 
 const JAVA_CLOUDS_TEXTURE: &[u8] = include_bytes!("Diskksks.png");
 
-fn get_current_mcver(man: ndk::asset::AssetManager) -> Option<MinecraftVersion> {
-    let mut file = match get_uitext(man) {
-        Some(asset) => asset,
-        None => {
-            log::error!("Shader fixing is disabled as no mc version was found");
-            return None;
-        }
-    };
-    let mut buf = Vec::with_capacity(file.length());
-    if let Err(e) = file.read_to_end(&mut buf) {
-        log::error!("Something is wrong with AssetManager, mc detection failed: {e}");
-        return None;
-    };
-    for version in materialbin::ALL_VERSIONS {
-        if buf
-            .pread_with::<CompiledMaterialDefinition>(0, version)
-            .is_ok()
-        {
-            log::info!("Mc version is {version}");
-            return Some(version);
-        };
-    }
-    None
-}
-
-fn get_uitext(man: ndk::asset::AssetManager) -> Option<Asset> {
-    const NEW: &CStr = c"assets/renderer/materials/UIText.material.bin";
-    const OLD: &CStr = c"renderer/materials/UIText.material.bin";
-    for path in [NEW, OLD] {
-        if let Some(asset) = man.open(path) {
-            return Some(asset);
-        }
-    }
-    None
-}
-
-macro_rules! folder_list {
-    ($( apk: $apk_folder:literal -> pack: $pack_folder:expr),
-        *,
-    ) => {
-        [
-            $(($apk_folder, $pack_folder)),*,
-        ]
-    }
-}
-
 fn get_no_fog_material_data(filename: &str) -> Option<&'static [u8]> {
     if !is_no_fog_enabled() {
         return None;
@@ -575,7 +561,7 @@ pub(crate) unsafe fn open(
         
         if let Some(custom_cape_data) = load_custom_cape_texture() {
             let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(custom_cape_data));
+            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(custom_cape_data.into()));
             return aasset;
         } else {
             log::warn!("Custom cape texture not found, blocking cape_invisible texture");
@@ -625,7 +611,7 @@ pub(crate) unsafe fn open(
         
         if let Some(modified_data) = modify_player_entity_json(&original_data) {
             let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(modified_data));
+            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(modified_data.into()));
             return aasset;
         } else {
             log::warn!("Failed to modify player.entity.json, using original");
@@ -638,7 +624,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting splashes.json with custom content");
         let buffer = CUSTOM_SPLASHES_JSON.as_bytes().to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -647,7 +633,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting loading_messages.json with custom content");
         let buffer = CUSTOM_LOADING_MESSAGES_JSON.as_bytes().to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -656,7 +642,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting clouds texture with Java clouds texture: {}", c_path.display());
         let buffer = JAVA_CLOUDS_TEXTURE.to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
 
@@ -665,7 +651,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting steve.png with classic Steve texture: {}", c_path.display());
         let buffer = CLASSIC_STEVE_TEXTURE.to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -673,7 +659,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting alex.png with classic Alex texture: {}", c_path.display());
         let buffer = CLASSIC_ALEX_TEXTURE.to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -681,7 +667,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting skins.json with classic skins content: {}", c_path.display());
         let buffer = CUSTOM_SKINS_JSON.as_bytes().to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -690,7 +676,7 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting cape render controller file with cape content: {}", c_path.display());
         let buffer = RENDER_JSON.as_bytes().to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -698,65 +684,44 @@ pub(crate) unsafe fn open(
         log::info!("Intercepting  ui3dmaterial file with new content: {}", c_path.display());
         let buffer = CUSTOM_BLOCKOUTLINE.as_bytes().to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
     
-    // No hurt cam camera replacements
-    if is_no_hurt_cam_enabled() {
-        let path_str = c_path.to_string_lossy();
-        
-        if path_str.contains("cameras/") {
-            if os_filename == "first_person.json" {
-                log::info!("Intercepting cameras/first_person.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_FIRST_PERSON_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
-            }
-            
-            if os_filename == "third_person.json" {
-                log::info!("Intercepting cameras/third_person.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_THIRD_PERSON_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
-            }
-            
-            if os_filename == "third_person_front.json" {
-                log::info!("Intercepting cameras/third_person_front.json with custom content (nohurtcam enabled)");
-                let buffer = CUSTOM_THIRD_PERSON_FRONT_JSON.as_bytes().to_vec();
-                let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-                wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-                return aasset;
-            }
+    // Camera presets, edited from the vanilla files
+    let filename_str = os_filename.to_string_lossy();
+    if camera::enabled() && camera::is_camera_file(&c_path.to_string_lossy(), &filename_str) {
+        let Some(original) = asset_bytes(aasset) else {
+            log::error!("Failed to read original {}", c_path.display());
+            return aasset;
+        };
+        if let Some(patched) = camera::patch(&filename_str, original) {
+            log::info!("Intercepting {} with camera preset", c_path.display());
+            let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
+            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(patched.into()));
+            return aasset;
         }
     }
 
     // Material replacements
-    let filename_str = os_filename.to_string_lossy();
     if let Some(no_fog_data) = get_no_fog_material_data(&filename_str) {
         log::info!("Intercepting {} with no-fog material (no-fog enabled)", filename_str);
-        let buffer = no_fog_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
+        let buffer = builtin_material(man, "no_fog/RenderChunk", no_fog_data);
+        return serve_material(man, aasset, c_path, buffer);
     }
     
     if let Some(night_vision_data) = get_nightvision_material_data(&filename_str) {
         log::info!("Intercepting {} with night-vision material (night-vision enabled)", filename_str);
-        let buffer = night_vision_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
+        let buffer = builtin_material(man, "night_vision/RenderChunk", night_vision_data);
+        return serve_material(man, aasset, c_path, buffer);
     }
     
     if let Some(shadows_material_data) = get_shadows_material_data(&filename_str) {
         log::info!("Intercepting {} with shadow material (noshadows enabled)", filename_str);
         let buffer = shadows_material_data.to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
     
@@ -764,7 +729,7 @@ pub(crate) unsafe fn open(
     log::info!("Intercepting shield animation with side shield animation: {}", c_path.display());
     let buffer = FLIPBOOK_ANIMATION_JSON.to_vec();
     let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
     return aasset;
 }
 
@@ -772,23 +737,21 @@ if is_particles_disabler_file(c_path) {
     log::info!("Intercepting common json with no particles: {}", c_path.display());
     let buffer = COMMON_JSON.to_vec();
     let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
     return aasset;
 }
     
     if let Some(java_cubemap_data) = get_java_cubemap_material_data(&filename_str) {
         log::info!("Intercepting {} with java-cubemap material (java-cubemap enabled)", filename_str);
-        let buffer = java_cubemap_data.to_vec();
-        let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
-        return aasset;
+        let buffer = builtin_material(man, "java_cubemap/LegacyCubemap", java_cubemap_data);
+        return serve_material(man, aasset, c_path, buffer);
     }
     
     if let Some(title_png_data) = get_title_png_data(&filename_str) {
         log::info!("Intercepting {} with xelo title png (xelo-title enabled)", filename_str);
         let buffer = title_png_data.to_vec();
         let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+        wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer.into()));
         return aasset;
     }
 
@@ -798,51 +761,145 @@ if is_particles_disabler_file(c_path) {
         Err(_e) => c_path,
     };
     
-    let replacement_list = folder_list! {
-        apk: "gui/dist/hbui/" -> pack: "hbui/",
-        apk: "skin_packs/persona/" -> pack: "persona/",
-        apk: "renderer/" -> pack: "renderer/",
-        apk: "resource_packs/vanilla/cameras/" -> pack: "vanilla_cameras/",
-    };
-    
-    for replacement in replacement_list {
-        if let Ok(file) = stripped.strip_prefix(replacement.0) {
-            cxx::let_cxx_string!(cxx_out = "");
-            let loadfn = match crate::RPM_LOAD.get() {
-                Some(ptr) => ptr,
-                None => {
-                    log::warn!("ResourcePackManager fn is not ready yet?");
-                    return aasset;
-                }
-            };
+    let config = get_config();
+    for redirect in &config.pack_redirects {
+        if let Ok(file) = stripped.strip_prefix(&redirect.apk_prefix) {
+            if crate::PACK_REDIRECTS_UNAVAILABLE.load(std::sync::atomic::Ordering::Acquire) {
+                log::trace!("Pack redirects are unavailable, serving {} from the apk", c_path.display());
+                return vanilla_asset(man, aasset, os_filename);
+            }
+            let managers = crate::rpm::ResourcePackManager::selected();
+            if managers.is_empty() {
+                log::warn!("ResourcePackManager is not ready yet?");
+                return vanilla_asset(man, aasset, os_filename);
+            }
             let mut arraybuf = [0; 128];
-            let file_path = opt_path_join(&mut arraybuf, &[Path::new(replacement.1), file]);
-            let packm_ptr = crate::PACKM_OBJ.load(std::sync::atomic::Ordering::Acquire);
-            let resource_loc = ResourceLocation::from_str(file_path.as_ref());
+            let file_path = opt_path_join(&mut arraybuf, &[Path::new(&redirect.pack_prefix), file]);
             log::info!("loading rpck file: {:#?}", &file_path);
-            if packm_ptr.is_null() {
-                log::error!("ResourcePackManager ptr is null");
-                return aasset;
-            }
-            loadfn(packm_ptr, resource_loc, cxx_out.as_mut());
-            if cxx_out.is_empty() {
+            let loaded = managers
+                .iter()
+                .find_map(|rpm| rpm.load_from(redirect.file_system, &file_path));
+            let Some(data) = loaded else {
                 log::info!("File was not found");
-                return aasset;
-            }
-            let buffer = if os_filename.as_encoded_bytes().ends_with(b".material.bin") {
-                match process_material(man, cxx_out.as_bytes()) {
-                    Some(updated) => updated,
-                    None => cxx_out.as_bytes().to_vec(),
-                }
-            } else {
-                cxx_out.as_bytes().to_vec()
+                return match redirect.fallback {
+                    RedirectFallback::Vanilla => vanilla_asset(man, aasset, os_filename),
+                    RedirectFallback::Block => {
+                        if !aasset.is_null() {
+                            ndk_sys::AAsset_close(aasset);
+                        }
+                        std::ptr::null_mut()
+                    }
+                };
             };
+            let is_material = os_filename.as_encoded_bytes().ends_with(b".material.bin");
+            if is_material {
+                let material = if redirect.process_material {
+                    process_material(man, data.into())
+                } else {
+                    check_material(man, data.into())
+                };
+                return serve_material(man, aasset, c_path, material);
+            }
             let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
-            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(buffer));
+            wanted_lock.insert(AAssetPtr(aasset), Cursor::new(data.into()));
             return aasset;
         }
     }
-    return aasset;
+    vanilla_asset(man, aasset, os_filename)
+}
+
+// A material that doesn't parse for the running version can take down the
+// renderer, so those are swapped for the vanilla file instead
+unsafe fn serve_material(
+    man: *mut AAssetManager,
+    aasset: *mut AAsset,
+    path: &Path,
+    material: CheckedMaterial,
+) -> *mut AAsset {
+    let data = match material {
+        Ok(data) => data,
+        Err(e) => {
+            log::error!("Not serving {}: {e}", path.display());
+            status::set_material_failure(&path.to_string_lossy(), e);
+            let Some(filename) = path.file_name() else {
+                return aasset;
+            };
+            return vanilla_asset(man, aasset, filename);
+        }
+    };
+    let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
+    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(data));
+    aasset
+}
+
+// Full parse as the running version, for materials that skip conversion
+fn check_material(man: *mut AAssetManager, data: AssetData) -> CheckedMaterial {
+    let Some(mcver) = current_mcver(man) else {
+        // Nothing to check against, strict mode takes no chances
+        if get_config().strict_materials {
+            return Err("the game's material format is unknown".to_string());
+        }
+        return Ok(data);
+    };
+    match data.as_ref().pread_with::<CompiledMaterialDefinition>(0, mcver) {
+        Ok(_) => Ok(data),
+        Err(e) => Err(format!("does not parse as {mcver}: {e}")),
+    }
+}
+
+// Contents of a real asset, the game never sees its read position move
+unsafe fn asset_bytes<'a>(aasset: *mut AAsset) -> Option<&'a [u8]> {
+    if aasset.is_null() {
+        return None;
+    }
+    let buffer = ndk_sys::AAsset_getBuffer(aasset);
+    if buffer.is_null() {
+        return None;
+    }
+    let len = ndk_sys::AAsset_getLength64(aasset) as usize;
+    Some(core::slice::from_raw_parts(buffer as *const u8, len))
+}
+
+// Materials from the apk that have patches get edited, anything else is
+// passed through untouched. Patched output goes through the material cache
+// so a material is only patched once per game and patch version, unless one
+// of its patches failed. That one gets patched and reported on every open.
+unsafe fn vanilla_asset(man: *mut AAssetManager, aasset: *mut AAsset, filename: &OsStr) -> *mut AAsset {
+    let Some(material) = filename.to_str().and_then(|name| name.strip_suffix(".material.bin")) else {
+        return aasset;
+    };
+    if aasset.is_null() || !material_patch::has_patches(material) {
+        return aasset;
+    }
+    let Some(mcver) = current_mcver(man) else {
+        return aasset;
+    };
+    let Some(data) = asset_bytes(aasset) else {
+        log::warn!("Could not read {material} for patching");
+        return aasset;
+    };
+    let cache_tag = material_patch::cache_tag(material);
+    let patched = match material_cache::get(data, mcver, &cache_tag) {
+        Some(cached) => AssetData::Mapped(cached),
+        None => {
+            let key = (material.to_string(), hash::fnv1a64(data));
+            if UNPATCHED_MATERIALS.lock().unwrap().contains(&key) {
+                return aasset;
+            }
+            let Some(patched) = material_patch::patch(material, data, mcver) else {
+                // Patches that don't apply won't start applying until restart
+                UNPATCHED_MATERIALS.lock().unwrap().insert(key);
+                return aasset;
+            };
+            if patched.complete {
+                material_cache::put(data, mcver, &cache_tag, &patched.data);
+            }
+            patched.data.into()
+        }
+    };
+    let mut wanted_lock = WANTED_ASSETS.lock().unwrap();
+    wanted_lock.insert(AAssetPtr(aasset), Cursor::new(patched));
+    aasset
 }
 
 fn opt_path_join<'a>(bytes: &'a mut [u8; 128], paths: &[&Path]) -> Cow<'a, CStr> {
@@ -866,39 +923,52 @@ fn opt_path_join<'a>(bytes: &'a mut [u8; 128], paths: &[&Path]) -> Cow<'a, CStr>
     Cow::Borrowed(guh)
 }
 
-fn process_material(man: *mut AAssetManager, data: &[u8]) -> Option<Vec<u8>> {
-    let mcver = MC_VERSION.get_or_init(|| {
-        let pointer = match std::ptr::NonNull::new(man) {
-            Some(yay) => yay,
-            None => {
-                log::warn!("AssetManager is null?, preposterous, mc detection failed");
-                return None;
-            }
-        };
-        let manager = unsafe { ndk::asset::AssetManager::from_ptr(pointer) };
-        get_current_mcver(manager)
-    });
-    let mcver = (*mcver)?;
-    for version in materialbin::ALL_VERSIONS {
-        let material: CompiledMaterialDefinition = match data.pread_with(0, version) {
-            Ok(data) => data,
-            Err(e) => {
-                log::trace!("[version] Parsing failed: {e}");
-                continue;
-            }
-        };
-        if version == mcver {
-            return None;
-        }
-        let mut output = Vec::with_capacity(data.len());
-        if let Err(e) = material.write(&mut output, mcver) {
-            log::trace!("[version] Write error: {e}");
-            return None;
-        }
-        return Some(output);
+// Embedded materials are compiled for one game version, run them through the
+// same conversion as pack materials so they work on the others too. The
+// result is kept either way, a built in that fails won't start working.
+fn builtin_material(man: *mut AAssetManager, name: &'static str, data: &'static [u8]) -> CheckedMaterial {
+    let mut converted = CONVERTED_BUILTINS.lock().unwrap();
+    if let Some(cached) = converted.get(name) {
+        return cached.clone();
+    }
+    let output = process_material(man, AssetData::Static(data));
+    if output.as_ref().is_ok_and(|output| output.as_ref() != data) {
+        log::info!("Converted built in material {name} to the running version");
     }
+    converted.insert(name, output.clone());
+    output
+}
+
+fn current_mcver(man: *mut AAssetManager) -> Option<MinecraftVersion> {
+    game_version::detect(man).material
+}
 
-    None
+// Converts a material to the running version when it needs it. Anything
+// that comes back Ok parsed as that version, conversions are checked once
+// before they go into the cache so cache hits are served as they are.
+fn process_material(man: *mut AAssetManager, data: AssetData) -> CheckedMaterial {
+    let Some(mcver) = current_mcver(man) else {
+        return check_material(man, data);
+    };
+    let input = data.as_ref();
+    let strip = shader_platforms::enabled();
+    let cache_tag = shader_platforms::cache_tag();
+    // Bytes saved by a cached file were counted when it was converted
+    if let Some(cached) = material_cache::get(input, mcver, &cache_tag) {
+        return Ok(AssetData::Mapped(cached));
+    }
+    let conversion =
+        materials::convert(input, mcver, |material| if strip { shader_platforms::strip(material) } else { 0 })?;
+    let Some(output) = conversion.output else {
+        // Already in the running format, parsing it just now checked it
+        return Ok(data);
+    };
+    if conversion.stripped_bytes != 0 {
+        log::info!("Saved {} bytes by stripping shader platforms", conversion.stripped_bytes);
+        status::add_shader_bytes_stripped(conversion.stripped_bytes as u64);
+    }
+    material_cache::put(input, mcver, &cache_tag, &output);
+    Ok(output.into())
 }
 
 pub(crate) unsafe fn seek64(aasset: *mut AAsset, off: off64_t, whence: libc::c_int) -> off64_t {
@@ -946,7 +1016,7 @@ pub(crate) unsafe fn len(aasset: *mut AAsset) -> off_t {
         Some(file) => file,
         None => return ndk_sys::AAsset_getLength(aasset),
     };
-    file.get_ref().len() as off_t
+    file.get_ref().as_ref().len() as off_t
 }
 
 pub(crate) unsafe fn len64(aasset: *mut AAsset) -> off64_t {
@@ -955,7 +1025,7 @@ pub(crate) unsafe fn len64(aasset: *mut AAsset) -> off64_t {
         Some(file) => file,
         None => return ndk_sys::AAsset_getLength64(aasset),
     };
-    file.get_ref().len() as off64_t
+    file.get_ref().as_ref().len() as off64_t
 }
 
 pub(crate) unsafe fn rem(aasset: *mut AAsset) -> off_t {
@@ -964,7 +1034,7 @@ pub(crate) unsafe fn rem(aasset: *mut AAsset) -> off_t {
         Some(file) => file,
         None => return ndk_sys::AAsset_getRemainingLength(aasset),
     };
-    (file.get_ref().len() - file.position() as usize) as off_t
+    (file.get_ref().as_ref().len() - file.position() as usize) as off_t
 }
 
 pub(crate) unsafe fn rem64(aasset: *mut AAsset) -> off64_t {
@@ -973,7 +1043,7 @@ pub(crate) unsafe fn rem64(aasset: *mut AAsset) -> off64_t {
         Some(file) => file,
         None => return ndk_sys::AAsset_getRemainingLength64(aasset),
     };
-    (file.get_ref().len() - file.position() as usize) as off64_t
+    (file.get_ref().as_ref().len() - file.position() as usize) as off64_t
 }
 
 pub(crate) unsafe fn close(aasset: *mut AAsset) {
@@ -989,7 +1059,7 @@ pub(crate) unsafe fn get_buffer(aasset: *mut AAsset) -> *const libc::c_void {
         Some(file) => file,
         None => return ndk_sys::AAsset_getBuffer(aasset),
     };
-    file.get_mut().as_mut_ptr().cast()
+    file.get_ref().as_ref().as_ptr().cast()
 }
 
 pub(crate) unsafe fn fd_dummy(
@@ -1030,7 +1100,7 @@ pub(crate) unsafe fn is_alloc(aasset: *mut AAsset) -> libc::c_int {
     }
 }
 
-fn seek_facade(offset: i64, whence: libc::c_int, file: &mut Cursor<Vec<u8>>) -> i64 {
+fn seek_facade(offset: i64, whence: libc::c_int, file: &mut Cursor<AssetData>) -> i64 {
     let offset = match whence {
         libc::SEEK_SET => {
             let u64_off = match u64::try_from(offset) {
@@ -0,0 +1,176 @@
+// Host side companion for shader work: look inside material bins, convert
+// them and try material patches before a file ever goes to a phone.
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use mtbinloader2::{material_patch, materials};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, fs, path::Path, process::ExitCode};
+
+const USAGE: &str = "usage:
+  materialbin-tool dump <file.material.bin>
+  materialbin-tool convert <in> <out> <version>
+  materialbin-tool patch <in> <patch.json> <out>
+  materialbin-tool diff <a> <b>
+  materialbin-tool versions";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["dump", input] => dump(input),
+        ["convert", input, output, version] => convert(input, output, version),
+        ["patch", input, patch, output] => patch(input, patch, output),
+        ["diff", a, b] => diff(a, b),
+        ["versions"] => {
+            for version in materialbin::ALL_VERSIONS {
+                println!("{version}");
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read_material(path: &str) -> Result<(CompiledMaterialDefinition, MinecraftVersion, usize), String> {
+    let data = fs::read(path).map_err(|e| format!("Can't read {path}: {e}"))?;
+    let (material, version) =
+        materials::parse(&data).ok_or_else(|| format!("{path} matched no known material format"))?;
+    Ok((material, version, data.len()))
+}
+
+fn parse_version(name: &str) -> Result<MinecraftVersion, String> {
+    materialbin::ALL_VERSIONS
+        .iter()
+        .copied()
+        .find(|version| version.to_string() == name)
+        .ok_or_else(|| format!("Unknown version {name}, see `materialbin-tool versions`"))
+}
+
+fn dump(input: &str) -> Result<(), String> {
+    let (material, version, _) = read_material(input)?;
+    let mut passes = Map::new();
+    for (pass_name, pass) in &material.passes {
+        let variants: Vec<Value> = pass
+            .variants
+            .iter()
+            .map(|variant| {
+                let flags: BTreeMap<&str, &str> = variant
+                    .flags
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+                    .collect();
+                let shaders: Vec<Value> = variant
+                    .shader_codes
+                    .iter()
+                    .map(|(stage, code)| {
+                        let source = material_patch::shader_source(&code.bgfx_shader_data)
+                            .filter(|_| material_patch::is_text_platform(&stage.platform_name));
+                        json!({
+                            "stage": stage.stage_name,
+                            "platform": stage.platform_name,
+                            "size": code.bgfx_shader_data.len(),
+                            "defines": source.map(material_patch::shader_defines),
+                            "uniforms": material_patch::shader_uniforms(&code.bgfx_shader_data),
+                        })
+                    })
+                    .collect();
+                json!({ "flags": flags, "shaders": shaders })
+            })
+            .collect();
+        passes.insert(pass_name.clone(), json!({ "variants": variants }));
+    }
+    let dump = json!({
+        "name": material.name,
+        "version": version.to_string(),
+        "passes": passes,
+    });
+    println!("{}", serde_json::to_string_pretty(&dump).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+fn convert(input: &str, output: &str, version: &str) -> Result<(), String> {
+    let target = parse_version(version)?;
+    let data = fs::read(input).map_err(|e| format!("Can't read {input}: {e}"))?;
+    let conversion = materials::convert(&data, target, |_| 0).map_err(|e| format!("{input} {e}"))?;
+    let converted = conversion.output.as_deref().unwrap_or(&data);
+    fs::write(output, converted).map_err(|e| format!("Can't write {output}: {e}"))?;
+    eprintln!("Converted {input} from {} to {target}", conversion.from);
+    Ok(())
+}
+
+fn patch(input: &str, patch_path: &str, output: &str) -> Result<(), String> {
+    let patch_path = Path::new(patch_path);
+    let dir = patch_path.parent().unwrap_or(Path::new("."));
+    let patch = material_patch::load_patch(dir, patch_path)?;
+    let (mut material, version, size) = read_material(input)?;
+    if patch.material != material.name {
+        eprintln!("Warning: patch is for {}, this is {}", patch.material, material.name);
+    }
+    let edited = material_patch::apply(&patch, &mut material)?;
+    let data = materials::write(&material, version, size)?;
+    fs::write(output, &data).map_err(|e| format!("Can't write {output}: {e}"))?;
+    eprintln!("Patched {edited} shaders");
+    Ok(())
+}
+
+// Shaders keyed by pass, variant index, stage and platform
+fn shader_map(material: &CompiledMaterialDefinition) -> BTreeMap<String, &[u8]> {
+    let mut shaders = BTreeMap::new();
+    for (pass_name, pass) in &material.passes {
+        for (index, variant) in pass.variants.iter().enumerate() {
+            for (stage, code) in &variant.shader_codes {
+                let key = format!("{pass_name}[{index}] {} {}", stage.stage_name, stage.platform_name);
+                shaders.insert(key, code.bgfx_shader_data.as_slice());
+            }
+        }
+    }
+    shaders
+}
+
+fn diff(a: &str, b: &str) -> Result<(), String> {
+    let (material_a, version_a, _) = read_material(a)?;
+    let (material_b, version_b, _) = read_material(b)?;
+    if material_a.name != material_b.name {
+        println!("name: {} -> {}", material_a.name, material_b.name);
+    }
+    if version_a != version_b {
+        println!("version: {version_a} -> {version_b}");
+    }
+    let shaders_a = shader_map(&material_a);
+    let shaders_b = shader_map(&material_b);
+    let mut differences = 0;
+    for (key, blob_a) in &shaders_a {
+        let Some(blob_b) = shaders_b.get(key) else {
+            println!("- {key}");
+            differences += 1;
+            continue;
+        };
+        if blob_a == blob_b {
+            continue;
+        }
+        differences += 1;
+        match (material_patch::shader_source(blob_a), material_patch::shader_source(blob_b)) {
+            (Some(source_a), Some(source_b)) if source_a != source_b => {
+                let changed = source_a
+                    .lines()
+                    .zip(source_b.lines())
+                    .position(|(line_a, line_b)| line_a != line_b)
+                    .unwrap_or(source_a.lines().count().min(source_b.lines().count()));
+                println!("~ {key}: source differs from line {}", changed + 1);
+            }
+            _ => println!("~ {key}: {} -> {} bytes", blob_a.len(), blob_b.len()),
+        }
+    }
+    for key in shaders_b.keys().filter(|key| !shaders_a.contains_key(*key)) {
+        println!("+ {key}");
+        differences += 1;
+    }
+    println!("{differences} shaders differ");
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::config::{get_config, is_no_hurt_cam_enabled, CameraConfig};
+use serde_json::{Map, Value};
+
+// Camera presets are edits on the vanilla camera files, so components added
+// in newer versions survive and only the ones we care about change
+
+const CAMERA_FILES: &[&str] = &["first_person.json", "third_person.json", "third_person_front.json"];
+
+pub fn is_camera_file(path: &str, file_name: &str) -> bool {
+    path.contains("cameras/") && CAMERA_FILES.contains(&file_name)
+}
+
+// True when anything would change the vanilla cameras at all
+pub fn enabled() -> bool {
+    is_no_hurt_cam_enabled() || get_config().camera != CameraConfig::default()
+}
+
+pub fn patch(file_name: &str, data: &[u8]) -> Option<Vec<u8>> {
+    let mut json: Value = match serde_json::from_slice(data) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Failed to parse {file_name}: {e}");
+            return None;
+        }
+    };
+    let Some(components) = json
+        .pointer_mut("/minecraft:camera_entity/components")
+        .and_then(Value::as_object_mut)
+    else {
+        log::error!("{file_name} has no camera components");
+        return None;
+    };
+    if is_no_hurt_cam_enabled() {
+        components.remove("minecraft:gameplay_affects_fov");
+        components.retain(|name, _| !name.contains("shake"));
+    }
+    apply_camera_config(&get_config().camera, file_name != "first_person.json", components);
+    match serde_json::to_vec(&json) {
+        Ok(patched) => Some(patched),
+        Err(e) => {
+            log::error!("Failed to serialize {file_name}: {e}");
+            None
+        }
+    }
+}
+
+fn apply_camera_config(camera: &CameraConfig, third_person: bool, components: &mut Map<String, Value>) {
+    let mut set = |component: &str, field: &str, value: Value| {
+        let Some(component) = components.get_mut(component).and_then(Value::as_object_mut) else {
+            log::warn!("Camera has no {component}, not setting {field}");
+            return;
+        };
+        component.insert(field.to_string(), value);
+    };
+    if let Some(fov) = camera.fov {
+        set("minecraft:camera", "field_of_view", fov.into());
+    }
+    if let Some(near) = camera.near_clipping_plane {
+        set("minecraft:camera", "near_clipping_plane", near.into());
+    }
+    if let Some(far) = camera.far_clipping_plane {
+        set("minecraft:camera", "far_clipping_plane", far.into());
+    }
+    if let Some(view) = camera.view_offset {
+        set("minecraft:camera_offset", "view", view.to_vec().into());
+    }
+    if !third_person {
+        return;
+    }
+    if let Some(radius) = camera.third_person_radius {
+        set("minecraft:camera_orbit", "radius", radius.into());
+    }
+    if let Some(entity) = camera.third_person_offset {
+        set("minecraft:camera_offset", "entity", entity.to_vec().into());
+    }
+}
@@ -1,13 +1,22 @@
 use std::{
     fs::{self, File},
-    io::{Read, Write},
-    path::Path,
-    sync::OnceLock,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::{hash, ResourceFileSystem};
 
 // Config structure
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[schemars(title = "Xelo mod config")]
 pub struct ModConfig {
     #[serde(rename = "Nohurtcam")]
     pub no_hurt_cam: bool,
@@ -44,11 +53,152 @@ pub struct ModConfig {
     
     #[serde(rename = "no_flipbook_animations")]
     pub no_flipbook_animations: bool,
+
+    // Libraries besides libminecraftpe whose asset reads should go through us
+    #[serde(rename = "asset_hook_libraries", default)]
+    pub asset_hook_libraries: Vec<String>,
+
+    // Hook every loaded library that imports AAsset functions
+    #[serde(rename = "asset_hook_all_modules", default)]
+    pub asset_hook_all_modules: bool,
+
+    // Hook libc file functions in libminecraftpe and apply fs_rules to them
+    #[serde(rename = "fs_hooks", default)]
+    pub fs_hooks: bool,
+
+    #[serde(rename = "fs_rules", default)]
+    pub fs_rules: Vec<FsRule>,
+
+    // Engine folders in the apk that resource packs are allowed to override
+    #[serde(rename = "pack_redirects", default = "default_pack_redirects")]
+    pub pack_redirects: Vec<PackRedirect>,
+
+    // Which ResourcePackManager serves pack redirects
+    #[serde(rename = "rpm_selection", default)]
+    pub rpm_selection: RpmSelection,
+
+    // Names of inline game hooks that should not be installed, only read at
+    // launch so world and server overrides can't turn hooks on or off
+    #[serde(rename = "disabled_hooks", default)]
+    pub disabled_hooks: Vec<String>,
+
+    // Keep converted material.bin files on disk between launches
+    #[serde(rename = "material_cache", default = "default_true")]
+    pub material_cache: bool,
+
+    // Least recently used materials are dropped past this size
+    #[serde(rename = "material_cache_max_mb", default = "default_material_cache_max_mb")]
+    pub material_cache_max_mb: u64,
+
+    // Drop every non ESSL shader from converted materials, which are pack
+    // materials and the built in ones. The apk's own materials are left alone.
+    #[serde(rename = "strip_shader_platforms", default)]
+    pub strip_shader_platforms: bool,
+
+    // Refuse every material replacement when the game's format is unknown
+    #[serde(rename = "strict_materials", default)]
+    pub strict_materials: bool,
+
+    // Applied on top of the vanilla camera files
+    #[serde(rename = "camera", default)]
+    pub camera: CameraConfig,
     // You can add more fields as needed
     // #[serde(rename = "CustomField")]
     // pub custom_field: bool,
 }
 
+// Applies to every path starting with `prefix`, first matching rule wins
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct FsRule {
+    pub prefix: String,
+    #[serde(flatten)]
+    pub action: FsAction,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FsAction {
+    // Swap the prefix for `target` and open that instead
+    Redirect { target: String },
+    // Pretend the file does not exist
+    Block,
+    // Serve `contents` from memory
+    Virtualize { contents: String },
+}
+
+// Files under `apk_prefix` are looked up as `pack_prefix` + the rest of the
+// path in the active resource packs
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct PackRedirect {
+    pub apk_prefix: String,
+    pub pack_prefix: String,
+    // Convert .material.bin files to the running game version
+    #[serde(default = "default_true")]
+    pub process_material: bool,
+    #[serde(default)]
+    pub fallback: RedirectFallback,
+    #[serde(default)]
+    pub file_system: ResourceFileSystem,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RedirectFallback {
+    // No pack has the file, the game gets the one from the apk
+    #[default]
+    Vanilla,
+    // No pack has the file, the game gets nothing
+    Block,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_material_cache_max_mb() -> u64 {
+    128
+}
+
+fn default_pack_redirects() -> Vec<PackRedirect> {
+    let redirect = |apk_prefix: &str, pack_prefix: &str| PackRedirect {
+        apk_prefix: apk_prefix.to_string(),
+        pack_prefix: pack_prefix.to_string(),
+        process_material: true,
+        fallback: RedirectFallback::Vanilla,
+        file_system: ResourceFileSystem::UserPackage,
+    };
+    vec![
+        redirect("gui/dist/hbui/", "hbui/"),
+        redirect("skin_packs/persona/", "persona/"),
+        redirect("renderer/", "renderer/"),
+        redirect("resource_packs/vanilla/cameras/", "vanilla_cameras/"),
+    ]
+}
+
+// Unset fields keep whatever the game ships
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub fov: Option<f64>,
+    pub near_clipping_plane: Option<f64>,
+    pub far_clipping_plane: Option<f64>,
+    pub view_offset: Option<[f64; 2]>,
+    pub third_person_radius: Option<f64>,
+    pub third_person_offset: Option<[f64; 3]>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RpmSelection {
+    // The first one constructed, which is what older versions always used
+    #[default]
+    First,
+    Last,
+    Index(usize),
+    // Ask every manager in construction order until one has the file
+    FirstMatch,
+}
+
 impl Default for ModConfig {
     fn default() -> Self {
         Self {
@@ -64,6 +214,18 @@ impl Default for ModConfig {
             no_shadows: false,
             no_flipbook_animations: false,
             white_block_outline: false,
+            asset_hook_libraries: Vec::new(),
+            asset_hook_all_modules: false,
+            fs_hooks: false,
+            fs_rules: Vec::new(),
+            pack_redirects: default_pack_redirects(),
+            rpm_selection: RpmSelection::default(),
+            disabled_hooks: Vec::new(),
+            material_cache: true,
+            material_cache_max_mb: default_material_cache_max_mb(),
+            strip_shader_platforms: false,
+            strict_materials: false,
+            camera: CameraConfig::default(),
         }
     }
 }
@@ -71,19 +233,226 @@ impl Default for ModConfig {
 // Global config instance
 static CONFIG: OnceLock<ModConfig> = OnceLock::new();
 
+// Base config with the overrides for the current world/server merged on top
+static ACTIVE_CONFIG: OnceLock<RwLock<ActiveConfig>> = OnceLock::new();
+
+struct ActiveConfig {
+    config: Arc<ModConfig>,
+    context_modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+// Written by the launcher when a world or server is joined, we have no hook
+// that tells us this so the file is polled
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+struct GameContext {
+    #[serde(default)]
+    world: Option<String>,
+    #[serde(default)]
+    server: Option<String>,
+}
+
+// Share codes look like "xelo-<base64url>", the payload is
+// [format version][FNV-1a of the json, LE][deflated json]
+const SHARE_CODE_PREFIX: &str = "xelo-";
+const SHARE_CODE_VERSION: u8 = 1;
+// Nobody has a config anywhere near this big, this just stops zip bombs
+const SHARE_CODE_MAX_JSON: usize = 64 * 1024;
+// Only what changes how the game looks travels in a share code, file rules,
+// hook and cache settings stay on the device that set them
+const SHARE_CODE_FIELDS: &[&str] = &[
+    "Nohurtcam",
+    "Nofog",
+    "particles_disabler",
+    "java_clouds",
+    "java_cubemap",
+    "classic_skins",
+    "night_vision",
+    "xelo_title",
+    "no_shadows",
+    "client_capes",
+    "white_block_outline",
+    "no_flipbook_animations",
+    "camera",
+];
+
+const CONTEXT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 // Config file path
-const CONFIG_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods";
+pub(crate) const CONFIG_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods";
 const CONFIG_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config.json";
+const CONFIG_BACKUP_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config.json.bak";
+const CONFIG_BROKEN_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config.json.broken";
+const CONFIG_ERRORS_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config_errors.txt";
+const CONFIG_SCHEMA_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/config.schema.json";
+const IMPORT_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/import.txt";
+const IMPORT_FAILED_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/import.txt.failed";
+const SHARE_CODE_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/share_code.txt";
+const CONTEXT_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/context.json";
+const WORLD_OVERRIDES_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/overrides/worlds";
+const SERVER_OVERRIDES_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/overrides/servers";
 
 pub fn init_config() {
-    let config = load_or_create_config();
+    let mut config = load_or_create_config();
+    if let Some(imported) = import_share_code_file(&config) {
+        config = imported;
+    }
     CONFIG.set(config).expect("Failed to set config");
+    for dir in [WORLD_OVERRIDES_DIR, SERVER_OVERRIDES_DIR] {
+        if let Err(e) = fs::create_dir_all(dir) {
+            log::warn!("Failed to create overrides directory {}: {}", dir, e);
+        }
+    }
+    let context_modified = context_modified_time();
+    let config = Arc::new(layered_config(&load_context()));
+    write_share_code(&config);
+    let active = ActiveConfig {
+        config,
+        context_modified,
+        last_check: Instant::now(),
+    };
+    let _ = ACTIVE_CONFIG.set(RwLock::new(active));
+}
+
+// Tests have none of the launcher's files, the first config set wins
+#[cfg(test)]
+pub fn init_test_config(config: ModConfig) {
+    let config = CONFIG.get_or_init(|| config);
+    ACTIVE_CONFIG.get_or_init(|| {
+        RwLock::new(ActiveConfig {
+            config: Arc::new(config.clone()),
+            context_modified: context_modified_time(),
+            last_check: Instant::now(),
+        })
+    });
 }
 
-pub fn get_config() -> &'static ModConfig {
+pub fn get_base_config() -> &'static ModConfig {
     CONFIG.get().expect("Config not initialized")
 }
 
+// Config that applies right now, this follows world and server changes so
+// don't hold on to it across asset opens
+pub fn get_config() -> Arc<ModConfig> {
+    let active = ACTIVE_CONFIG.get().expect("Config not initialized");
+    refresh_active_config(active);
+    active.read().unwrap().config.clone()
+}
+
+fn refresh_active_config(active: &RwLock<ActiveConfig>) {
+    if active.read().unwrap().last_check.elapsed() < CONTEXT_CHECK_INTERVAL {
+        return;
+    }
+    let mut active = active.write().unwrap();
+    // Someone else might have refreshed it while we waited for the lock
+    if active.last_check.elapsed() < CONTEXT_CHECK_INTERVAL {
+        return;
+    }
+    active.last_check = Instant::now();
+    let modified = context_modified_time();
+    if modified == active.context_modified {
+        return;
+    }
+    active.context_modified = modified;
+    let context = load_context();
+    log::info!("Game context changed: {:?}", context);
+    active.config = Arc::new(layered_config(&context));
+    write_share_code(&active.config);
+}
+
+fn context_modified_time() -> Option<SystemTime> {
+    fs::metadata(CONTEXT_FILE).and_then(|m| m.modified()).ok()
+}
+
+fn load_context() -> GameContext {
+    let contents = match fs::read_to_string(CONTEXT_FILE) {
+        Ok(contents) => contents,
+        Err(_) => return GameContext::default(),
+    };
+    match serde_json::from_str(&contents) {
+        Ok(context) => context,
+        Err(e) => {
+            log::warn!("Failed to parse {}: {}", CONTEXT_FILE, e);
+            GameContext::default()
+        }
+    }
+}
+
+fn layered_config(context: &GameContext) -> ModConfig {
+    let base = get_base_config();
+    let mut override_files = Vec::new();
+    if let Some(world) = &context.world {
+        override_files.push(override_path(WORLD_OVERRIDES_DIR, world));
+    }
+    if let Some(server) = &context.server {
+        // Allow both "host:port" and plain "host" files
+        override_files.push(override_path(SERVER_OVERRIDES_DIR, server));
+        if let Some((host, _port)) = server.rsplit_once(':') {
+            override_files.push(override_path(SERVER_OVERRIDES_DIR, host));
+        }
+    }
+    let mut merged = match serde_json::to_value(base) {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("Failed to serialize config for overrides: {}", e);
+            return base.clone();
+        }
+    };
+    let mut applied = false;
+    for path in override_files {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => continue,
+        };
+        match serde_json::from_str::<Value>(&contents) {
+            Ok(layer) => {
+                log::info!("Applying config override {}", path.display());
+                merge_json(&mut merged, layer);
+                applied = true;
+            }
+            Err(e) => log::warn!("Ignoring broken config override {}: {}", path.display(), e),
+        }
+    }
+    if !applied {
+        return base.clone();
+    }
+    match serde_json::from_value(merged) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!("Config overrides don't match the config format, ignoring them: {}", e);
+            base.clone()
+        }
+    }
+}
+
+fn override_path(dir: &str, key: &str) -> PathBuf {
+    // World names can contain pretty much anything
+    let name: String = key
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | '\0' => '_',
+            c => c,
+        })
+        .collect();
+    Path::new(dir).join(format!("{name}.json"))
+}
+
+fn merge_json(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
 fn load_or_create_config() -> ModConfig {
     // Create directory if it doesn't exist
     if let Err(e) = fs::create_dir_all(CONFIG_DIR) {
@@ -91,15 +460,70 @@ fn load_or_create_config() -> ModConfig {
         return ModConfig::default();
     }
 
+    if let Err(e) = write_config_schema() {
+        log::warn!("Failed to write config schema: {}", e);
+    }
+
+    let mut errors = Vec::new();
+    let config = load_config_with_fallback(&mut errors);
+    if let Err(e) = write_config_errors(&errors) {
+        log::warn!("Failed to write config errors: {}", e);
+    }
+    config
+}
+
+fn load_config_with_fallback(errors: &mut Vec<String>) -> ModConfig {
     // Try to load existing config
     if Path::new(CONFIG_FILE).exists() {
-        match load_config() {
-            Ok(config) => {
+        let load_error = match load_config(CONFIG_FILE) {
+            Ok((config, contents)) => {
                 log::info!("Loaded config from {}", CONFIG_FILE);
+                // Remember the last config that parsed so a broken write can be recovered
+                if let Err(e) = backup_config(&contents) {
+                    log::warn!("Failed to back up config: {}", e);
+                }
                 return config;
             }
             Err(e) => {
-                log::warn!("Failed to load config, using default: {}", e);
+                log::warn!("Failed to load config {}: {}", CONFIG_FILE, e);
+                e
+            }
+        };
+
+        // The primary file is there but broken, don't overwrite it with defaults
+        // if the last good copy is still around
+        match load_config(CONFIG_BACKUP_FILE) {
+            Ok((config, _)) => {
+                log::warn!(
+                    "{} could not be parsed, using backup config from {}",
+                    CONFIG_FILE,
+                    CONFIG_BACKUP_FILE
+                );
+                errors.push(format!("{}: {}", CONFIG_FILE, load_error));
+                errors.push(format!("Using backup config from {}", CONFIG_BACKUP_FILE));
+                return config;
+            }
+            Err(e) => {
+                log::warn!("Failed to load backup config, using default: {}", e);
+            }
+        }
+
+        // Defaults are about to replace it, keep the broken file around so the
+        // line and column in the report still point at the user's mistake
+        match fs::rename(CONFIG_FILE, CONFIG_BROKEN_FILE) {
+            Ok(()) => {
+                errors.push(format!("{}: {}", CONFIG_BROKEN_FILE, load_error));
+                errors.push(format!(
+                    "{} could not be parsed and was moved to {}, using default config",
+                    CONFIG_FILE, CONFIG_BROKEN_FILE
+                ));
+            }
+            Err(e) => {
+                log::warn!("Failed to move {} to {}: {}", CONFIG_FILE, CONFIG_BROKEN_FILE, e);
+                errors.push(format!("{}: {}", CONFIG_FILE, load_error));
+                errors.push("Using default config without saving it".to_string());
+                // Overwriting it now would lose what the user wrote
+                return ModConfig::default();
             }
         }
     }
@@ -115,20 +539,207 @@ fn load_or_create_config() -> ModConfig {
     default_config
 }
 
-fn load_config() -> Result<ModConfig, Box<dyn std::error::Error>> {
-    let mut file = File::open(CONFIG_FILE)?;
+fn load_config(path: &str) -> Result<(ModConfig, String), Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
     
-    let config: ModConfig = serde_json::from_str(&contents)?;
-    Ok(config)
+    let config: ModConfig =
+        serde_json::from_str(&contents).map_err(|e| describe_parse_error(&contents, &e))?;
+    Ok((config, contents))
+}
+
+// Turn a serde error into something a user can act on, serde only gives us
+// the position so the key has to be dug out of the text
+fn describe_parse_error(contents: &str, err: &serde_json::Error) -> String {
+    let mut message = format!("line {}, column {}", err.line(), err.column());
+    if let Some(key) = offending_key(contents, err) {
+        message.push_str(&format!(", key \"{}\"", key));
+    }
+    message.push_str(&format!(": {}", err));
+    message
+}
+
+fn offending_key(contents: &str, err: &serde_json::Error) -> Option<String> {
+    let text = err.to_string();
+    // "missing field `Nofog`" and "unknown field `x`" name the key themselves
+    if text.starts_with("missing field") || text.starts_with("unknown field") {
+        let (_, rest) = text.split_once('`')?;
+        let (key, _) = rest.split_once('`')?;
+        return Some(key.to_string());
+    }
+    if err.line() == 0 {
+        return None;
+    }
+    // Otherwise take the last `"key":` before the error position
+    let line = contents.lines().nth(err.line() - 1)?;
+    let end = line
+        .char_indices()
+        .nth(err.column())
+        .map_or(line.len(), |(idx, _)| idx);
+    let before_value = line[..end].rsplit_once(':')?.0.trim_end();
+    let key = before_value.strip_suffix('"')?;
+    let start = key.rfind('"')?;
+    Some(key[start + 1..].to_string())
+}
+
+fn write_config_errors(errors: &[String]) -> io::Result<()> {
+    if errors.is_empty() {
+        // Don't let the launcher show errors that were already fixed
+        return match fs::remove_file(CONFIG_ERRORS_FILE) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    let mut report = errors.join("\n");
+    report.push('\n');
+    write_atomic(CONFIG_ERRORS_FILE, report.as_bytes())
+}
+
+// The schema comes from the config types themselves, so every field added
+// to ModConfig shows up here with its real type and allowed values
+fn write_config_schema() -> Result<(), Box<dyn std::error::Error>> {
+    let schema = schemars::schema_for!(ModConfig);
+    let json = serde_json::to_string_pretty(&schema)?;
+    if fs::read(CONFIG_SCHEMA_FILE).is_ok_and(|old| old == json.as_bytes()) {
+        return Ok(());
+    }
+    write_atomic(CONFIG_SCHEMA_FILE, json.as_bytes())?;
+    Ok(())
+}
+
+pub fn export_share_code(config: &ModConfig) -> String {
+    // ModConfig only holds plain values, this can't fail
+    let mut features = serde_json::to_value(config).unwrap_or_default();
+    if let Value::Object(fields) = &mut features {
+        fields.retain(|name, _| SHARE_CODE_FIELDS.contains(&name.as_str()));
+    }
+    let json = serde_json::to_vec(&features).unwrap_or_default();
+    let mut payload = Vec::with_capacity(json.len() / 2 + 5);
+    payload.push(SHARE_CODE_VERSION);
+    payload.extend_from_slice(&hash::fnv1a32(&json).to_le_bytes());
+    payload.extend_from_slice(&miniz_oxide::deflate::compress_to_vec(&json, 9));
+    format!("{}{}", SHARE_CODE_PREFIX, URL_SAFE_NO_PAD.encode(payload))
+}
+
+fn write_share_code(config: &ModConfig) {
+    if let Err(e) = write_atomic(SHARE_CODE_FILE, export_share_code(config).as_bytes()) {
+        log::warn!("Failed to write share code: {}", e);
+    }
+}
+
+// Applies a share code on top of `base`, features the code has but we don't
+// know about get ignored so codes from newer builds still work
+pub fn import_share_code(base: &ModConfig, code: &str) -> Result<ModConfig, Box<dyn std::error::Error>> {
+    let code = code.trim();
+    let encoded = code
+        .strip_prefix(SHARE_CODE_PREFIX)
+        .ok_or("Not a share code, missing the xelo- prefix")?;
+    let payload = URL_SAFE_NO_PAD.decode(encoded)?;
+    let (&version, rest) = payload.split_first().ok_or("Share code is empty")?;
+    if version != SHARE_CODE_VERSION {
+        return Err(format!("Unsupported share code version {}", version).into());
+    }
+    if rest.len() < 4 {
+        return Err("Share code is truncated".into());
+    }
+    let (checksum, compressed) = rest.split_at(4);
+    let json = miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, SHARE_CODE_MAX_JSON)
+        .map_err(|e| format!("Share code is corrupted: {:?}", e.status))?;
+    if hash::fnv1a32(&json).to_le_bytes() != checksum {
+        return Err("Share code checksum mismatch, it was probably copied wrong".into());
+    }
+
+    let Value::Object(features) = serde_json::from_slice::<Value>(&json)? else {
+        return Err("Share code does not contain a config".into());
+    };
+    let mut merged = serde_json::to_value(base)?;
+    let Value::Object(known) = &mut merged else {
+        return Err("Config is not an object".into());
+    };
+    for (name, value) in features {
+        if !SHARE_CODE_FIELDS.contains(&name.as_str()) {
+            log::warn!("Ignoring \"{}\" from share code, it is not a shareable feature", name);
+            continue;
+        }
+        match known.get_mut(&name) {
+            Some(existing) => merge_json(existing, value),
+            None => log::warn!("Ignoring unknown feature \"{}\" from share code", name),
+        }
+    }
+    Ok(serde_json::from_value(merged)?)
+}
+
+fn import_share_code_file(base: &ModConfig) -> Option<ModConfig> {
+    let code = fs::read_to_string(IMPORT_FILE).ok()?;
+    let result = import_share_code(base, &code).and_then(|config| {
+        save_config(&config)?;
+        Ok(config)
+    });
+    // Only handle a code once, a good one would undo every later change and
+    // a bad one would fail again on every launch
+    match result {
+        Ok(config) => {
+            if let Err(e) = fs::remove_file(IMPORT_FILE) {
+                log::warn!("Failed to remove {}: {}", IMPORT_FILE, e);
+            }
+            log::info!("Imported config from share code");
+            Some(config)
+        }
+        Err(e) => {
+            log::error!("Failed to import share code from {}: {}", IMPORT_FILE, e);
+            if let Err(e) = fs::rename(IMPORT_FILE, IMPORT_FAILED_FILE) {
+                log::warn!("Failed to move {} to {}: {}", IMPORT_FILE, IMPORT_FAILED_FILE, e);
+            }
+            None
+        }
+    }
 }
 
 fn save_config(config: &ModConfig) -> Result<(), Box<dyn std::error::Error>> {
     let json = serde_json::to_string_pretty(config)?;
-    let mut file = File::create(CONFIG_FILE)?;
-    file.write_all(json.as_bytes())?;
-    file.sync_all()?;
+    // Keep whatever is there now as the backup, but only if it is actually usable
+    if let Ok((_, contents)) = load_config(CONFIG_FILE) {
+        backup_config(&contents)?;
+    }
+    write_atomic(CONFIG_FILE, json.as_bytes())?;
+    Ok(())
+}
+
+fn backup_config(contents: &str) -> io::Result<()> {
+    if fs::read(CONFIG_BACKUP_FILE).is_ok_and(|old| old == contents.as_bytes()) {
+        return Ok(());
+    }
+    write_atomic(CONFIG_BACKUP_FILE, contents.as_bytes())
+}
+
+// Write to a temp file next to the target and rename it over, so a crash or
+// a full disk can never leave a half written file behind. Every write gets
+// its own temp file, two threads writing the same path would otherwise
+// rename each other's half written data into place.
+pub(crate) fn write_atomic(path: &str, data: &[u8]) -> io::Result<()> {
+    static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+    let tmp_path = format!(
+        "{path}.{}.{}.tmp",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let result = (|| {
+        let mut file = File::options().write(true).create_new(true).open(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+        return result;
+    }
+    // Make the rename itself durable
+    if let Some(dir) = Path::new(path).parent() {
+        if let Ok(dir) = File::open(dir) {
+            let _ = dir.sync_all();
+        }
+    }
     Ok(())
 }
 
@@ -182,4 +793,66 @@ pub fn is_no_flipbook_animations_enabled() -> bool {
 // You can add more helper functions for other config values
 // pub fn is_custom_field_enabled() -> bool {
 //     get_config().custom_field
-// }
\ No newline at end of file
+// }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn share_codes_round_trip() {
+        let config = ModConfig {
+            no_fog: true,
+            xelo_title: false,
+            camera: CameraConfig {
+                fov: Some(90.0),
+                ..CameraConfig::default()
+            },
+            // Not shareable, must stay at the importer's value
+            fs_hooks: true,
+            ..ModConfig::default()
+        };
+        let code = export_share_code(&config);
+        assert!(code.starts_with(SHARE_CODE_PREFIX));
+
+        let imported = import_share_code(&ModConfig::default(), &format!(" {code}\n")).unwrap();
+        assert!(imported.no_fog);
+        assert!(!imported.xelo_title);
+        assert_eq!(imported.camera.fov, Some(90.0));
+        assert!(!imported.fs_hooks);
+    }
+
+    #[test]
+    fn share_codes_with_a_bad_checksum_are_rejected() {
+        let code = export_share_code(&ModConfig::default());
+        let mut payload = URL_SAFE_NO_PAD.decode(&code[SHARE_CODE_PREFIX.len()..]).unwrap();
+        payload[1] ^= 0xff;
+        let tampered = format!("{SHARE_CODE_PREFIX}{}", URL_SAFE_NO_PAD.encode(payload));
+        let err = import_share_code(&ModConfig::default(), &tampered).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{err}");
+        assert!(import_share_code(&ModConfig::default(), "not a code").is_err());
+    }
+
+    #[test]
+    fn merge_json_only_replaces_what_the_layer_sets() {
+        let mut base = json!({ "Nofog": false, "camera": { "fov": 70, "view_offset": [1, 2] } });
+        merge_json(&mut base, json!({ "camera": { "fov": 90, "view_offset": [3] }, "fs_hooks": true }));
+        assert_eq!(
+            base,
+            json!({ "Nofog": false, "camera": { "fov": 90, "view_offset": [3] }, "fs_hooks": true })
+        );
+    }
+
+    #[test]
+    fn offending_key_names_the_broken_key() {
+        let key = |contents: &str| {
+            let err = serde_json::from_str::<ModConfig>(contents).unwrap_err();
+            offending_key(contents, &err)
+        };
+        assert_eq!(key("{}").as_deref(), Some("Nohurtcam"));
+        let wrong_type = "{\n  \"Nohurtcam\": false,\n  \"Nofog\": \"yes\"\n}";
+        assert_eq!(key(wrong_type).as_deref(), Some("Nofog"));
+        assert_eq!(key("not json"), None);
+    }
+}
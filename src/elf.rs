@@ -0,0 +1,194 @@
+// Just enough ELF parsing to find our way around loaded libraries
+#[cfg(target_pointer_width = "64")]
+use libc::{Elf64_Ehdr as Ehdr, Elf64_Phdr as Phdr};
+#[cfg(target_pointer_width = "32")]
+use libc::{Elf32_Ehdr as Ehdr, Elf32_Phdr as Phdr};
+
+use scroll::{Pread, LE};
+use std::{
+    ffi::CStr,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+const IS_64: bool = cfg!(target_pointer_width = "64");
+const EHDR_SIZE: usize = if IS_64 { 64 } else { 52 };
+
+const PT_NOTE: u32 = 4;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+// Returns the hex encoded NT_GNU_BUILD_ID of the library whose ELF header is
+// mapped at `base` and whose vaddrs are relocated by `bias`
+pub unsafe fn build_id(base: usize, bias: usize) -> Option<String> {
+    let ehdr = &*(base as *const Ehdr);
+    if ehdr.e_ident[..4] != *b"\x7fELF" {
+        log::warn!("No ELF header at {base:#x}");
+        return None;
+    }
+    let phdrs = core::slice::from_raw_parts(
+        (base + ehdr.e_phoff as usize) as *const Phdr,
+        ehdr.e_phnum as usize,
+    );
+    phdrs
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_NOTE)
+        .find_map(|phdr| {
+            let notes = core::slice::from_raw_parts(
+                bias.wrapping_add(phdr.p_vaddr as usize) as *const u8,
+                phdr.p_memsz as usize,
+            );
+            find_build_id(notes)
+        })
+}
+
+fn find_build_id(mut notes: &[u8]) -> Option<String> {
+    // Each note is namesz, descsz, type, then the name and desc padded to 4 bytes
+    while notes.len() >= 12 {
+        let word = |at: usize| u32::from_ne_bytes(notes[at..at + 4].try_into().unwrap());
+        let namesz = word(0) as usize;
+        let descsz = word(4) as usize;
+        let kind = word(8);
+        let name_end = 12 + namesz;
+        let desc_start = 12 + namesz.next_multiple_of(4);
+        let desc_end = desc_start + descsz;
+        if desc_end > notes.len() {
+            return None;
+        }
+        if kind == NT_GNU_BUILD_ID && &notes[12..name_end] == b"GNU\0" {
+            let id = notes[desc_start..desc_end]
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            return Some(id);
+        }
+        let next = desc_start + descsz.next_multiple_of(4);
+        notes = notes.get(next..)?;
+    }
+    None
+}
+
+const DT_NULL: usize = 0;
+const DT_STRTAB: usize = 5;
+const DT_SONAME: usize = 14;
+
+// Reads DT_SONAME from a mapped dynamic section
+pub unsafe fn soname(bias: usize, dynamic: usize) -> Option<String> {
+    let mut entry = dynamic as *const [usize; 2];
+    let mut strtab = None;
+    let mut soname = None;
+    loop {
+        let [tag, value] = *entry;
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = Some(value),
+            DT_SONAME => soname = Some(value),
+            _ => {}
+        }
+        entry = entry.add(1);
+    }
+    let mut strtab = strtab?;
+    // glibc relocates these in place, bionic leaves them as vaddrs
+    if strtab < bias {
+        strtab += bias;
+    }
+    let name = CStr::from_ptr((strtab + soname?) as *const libc::c_char);
+    Some(name.to_string_lossy().into_owned())
+}
+
+// Section headers are not loaded into memory, so .text has to come from the
+// file on disk. Returns the vaddr and size of .text.
+pub fn text_section(path: &str) -> Option<(usize, usize)> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 64];
+    file.read_exact(&mut header[..EHDR_SIZE]).ok()?;
+    let header = &header[..];
+    if header[..4] != *b"\x7fELF" {
+        return None;
+    }
+    let (shoff, shentsize, shnum, shstrndx): (usize, usize, usize, usize) = if IS_64 {
+        (
+            header.pread_with::<u64>(0x28, LE).ok()? as usize,
+            header.pread_with::<u16>(0x3A, LE).ok()? as usize,
+            header.pread_with::<u16>(0x3C, LE).ok()? as usize,
+            header.pread_with::<u16>(0x3E, LE).ok()? as usize,
+        )
+    } else {
+        (
+            header.pread_with::<u32>(0x20, LE).ok()? as usize,
+            header.pread_with::<u16>(0x2E, LE).ok()? as usize,
+            header.pread_with::<u16>(0x30, LE).ok()? as usize,
+            header.pread_with::<u16>(0x32, LE).ok()? as usize,
+        )
+    };
+    if shentsize == 0 || shstrndx >= shnum {
+        return None;
+    }
+    let mut sections = vec![0u8; shentsize * shnum];
+    file.seek(SeekFrom::Start(shoff as u64)).ok()?;
+    file.read_exact(&mut sections).ok()?;
+    let section = |index: usize| -> Option<SectionHeader> {
+        let at = index * shentsize;
+        if IS_64 {
+            Some(SectionHeader {
+                name: sections.pread_with::<u32>(at, LE).ok()? as usize,
+                addr: sections.pread_with::<u64>(at + 16, LE).ok()? as usize,
+                offset: sections.pread_with::<u64>(at + 24, LE).ok()? as usize,
+                size: sections.pread_with::<u64>(at + 32, LE).ok()? as usize,
+            })
+        } else {
+            Some(SectionHeader {
+                name: sections.pread_with::<u32>(at, LE).ok()? as usize,
+                addr: sections.pread_with::<u32>(at + 12, LE).ok()? as usize,
+                offset: sections.pread_with::<u32>(at + 16, LE).ok()? as usize,
+                size: sections.pread_with::<u32>(at + 20, LE).ok()? as usize,
+            })
+        }
+    };
+    let strtab = section(shstrndx)?;
+    let mut names = vec![0u8; strtab.size];
+    file.seek(SeekFrom::Start(strtab.offset as u64)).ok()?;
+    file.read_exact(&mut names).ok()?;
+    (0..shnum).filter_map(section).find_map(|header| {
+        let name = CStr::from_bytes_until_nul(names.get(header.name..)?).ok()?;
+        (name.to_bytes() == b".text").then_some((header.addr, header.size))
+    })
+}
+
+struct SectionHeader {
+    name: usize,
+    addr: usize,
+    offset: usize,
+    size: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(kind: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        for word in [name.len() as u32, desc.len() as u32, kind] {
+            note.extend_from_slice(&word.to_ne_bytes());
+        }
+        note.extend_from_slice(name);
+        note.resize(12 + name.len().next_multiple_of(4), 0);
+        note.extend_from_slice(desc);
+        note.resize(note.len() + (desc.len().next_multiple_of(4) - desc.len()), 0);
+        note
+    }
+
+    #[test]
+    fn build_id_is_found_after_other_notes() {
+        let mut notes = note(1, b"Android\0", &[0; 5]);
+        notes.extend(note(NT_GNU_BUILD_ID, b"GNU\0", &[0xde, 0xad, 0xbe, 0xef, 0x01]));
+        assert_eq!(find_build_id(&notes).as_deref(), Some("deadbeef01"));
+    }
+
+    #[test]
+    fn build_id_needs_a_gnu_note() {
+        assert_eq!(find_build_id(&note(NT_GNU_BUILD_ID, b"Go\0", &[1, 2, 3, 4])), None);
+        let notes = note(NT_GNU_BUILD_ID, b"GNU\0", &[1, 2, 3, 4]);
+        assert_eq!(find_build_id(&notes[..notes.len() - 1]), None);
+        assert_eq!(find_build_id(&[]), None);
+    }
+}
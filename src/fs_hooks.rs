@@ -0,0 +1,312 @@
+use crate::{
+    config::{get_config, FsAction},
+    plthook::{replace_plt_functions, PltHooks},
+};
+use libc::{c_char, c_int, mode_t, FILE};
+use plt_rs::DynamicLibrary;
+use std::{
+    ffi::{CStr, CString},
+    ptr::null_mut,
+};
+
+// Worlds, external packs and games/com.mojang never touch AAssetManager, the
+// game reads them through plain libc calls. These hooks let fs_rules redirect,
+// block or fake any of those paths.
+
+enum FsRedirect {
+    Path(CString),
+    Block,
+    Virtual(Vec<u8>),
+}
+
+// Installs the libc hooks into `dyn_lib`, this is not tied to libminecraftpe
+// so any library's imports can be hooked
+pub fn install(dyn_lib: &DynamicLibrary) -> PltHooks {
+    let fs_fn_list = [
+        ("open", open_hook as *const u8),
+        ("open64", open_hook as *const u8),
+        ("__open_2", open_2_hook as *const u8),
+        ("openat", openat_hook as *const u8),
+        ("__openat_2", openat_2_hook as *const u8),
+        ("fopen", fopen_hook as *const u8),
+        ("fopen64", fopen_hook as *const u8),
+        ("stat", stat_hook as *const u8),
+        ("lstat", lstat_hook as *const u8),
+        ("access", access_hook as *const u8),
+    ];
+    replace_plt_functions(dyn_lib, fs_fn_list)
+}
+
+fn resolve(path: *const c_char) -> Option<FsRedirect> {
+    if path.is_null() {
+        return None;
+    }
+    let config = get_config();
+    if config.fs_rules.is_empty() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(path) }.to_bytes();
+    let rule = config
+        .fs_rules
+        .iter()
+        .find(|rule| matches_prefix(path, rule.prefix.as_bytes()))?;
+    let rest = &path[rule.prefix.len()..];
+    let redirect = match &rule.action {
+        FsAction::Redirect { target } => {
+            let mut new_path = target.as_bytes().to_vec();
+            new_path.extend_from_slice(rest);
+            match CString::new(new_path) {
+                Ok(new_path) => FsRedirect::Path(new_path),
+                Err(e) => {
+                    log::error!("Bad redirect target {target}: {e}");
+                    return None;
+                }
+            }
+        }
+        FsAction::Block => FsRedirect::Block,
+        FsAction::Virtualize { contents } => FsRedirect::Virtual(contents.as_bytes().to_vec()),
+    };
+    log::trace!("fs rule {} matched {}", rule.prefix, String::from_utf8_lossy(path));
+    Some(redirect)
+}
+
+// "/a/b" covers "/a/b" and everything under it, but not "/a/bc"
+fn matches_prefix(path: &[u8], prefix: &[u8]) -> bool {
+    let Some(rest) = path.strip_prefix(prefix) else {
+        return false;
+    };
+    rest.is_empty() || rest[0] == b'/' || prefix.ends_with(b"/")
+}
+
+// In memory file with the given contents, positioned at the start
+fn memfd(contents: &[u8]) -> c_int {
+    unsafe {
+        // Going through syscall since bionic only has memfd_create from API 30
+        let fd = libc::syscall(libc::SYS_memfd_create, c"xelo_virtual".as_ptr(), libc::MFD_CLOEXEC) as c_int;
+        if fd < 0 {
+            return -1;
+        }
+        let mut written = 0;
+        while written < contents.len() {
+            let n = libc::write(fd, contents[written..].as_ptr().cast(), contents.len() - written);
+            if n <= 0 {
+                libc::close(fd);
+                return -1;
+            }
+            written += n as usize;
+        }
+        libc::lseek(fd, 0, libc::SEEK_SET);
+        fd
+    }
+}
+
+fn set_errno(value: c_int) {
+    unsafe {
+        #[cfg(target_os = "android")]
+        {
+            *libc::__errno() = value;
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            *libc::__errno_location() = value;
+        }
+    }
+}
+
+unsafe extern "C" fn open_hook(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    match resolve(path) {
+        None => libc::open(path, flags, mode as libc::c_uint),
+        Some(FsRedirect::Path(new_path)) => libc::open(new_path.as_ptr(), flags, mode as libc::c_uint),
+        Some(FsRedirect::Block) => {
+            set_errno(libc::ENOENT);
+            -1
+        }
+        Some(FsRedirect::Virtual(contents)) => memfd(&contents),
+    }
+}
+
+// FORTIFY version of open, used when the flags don't need a mode. open64
+// and fopen64 share the plain hooks, bionic always opens with O_LARGEFILE.
+unsafe extern "C" fn open_2_hook(path: *const c_char, flags: c_int) -> c_int {
+    open_hook(path, flags, 0)
+}
+
+unsafe extern "C" fn openat_hook(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    // Rules are absolute, a path relative to some directory fd can't match
+    let relative = !path.is_null() && *path != b'/' as c_char && dirfd != libc::AT_FDCWD;
+    let redirect = if relative { None } else { resolve(path) };
+    match redirect {
+        None => libc::openat(dirfd, path, flags, mode as libc::c_uint),
+        Some(FsRedirect::Path(new_path)) => libc::openat(dirfd, new_path.as_ptr(), flags, mode as libc::c_uint),
+        Some(FsRedirect::Block) => {
+            set_errno(libc::ENOENT);
+            -1
+        }
+        Some(FsRedirect::Virtual(contents)) => memfd(&contents),
+    }
+}
+
+unsafe extern "C" fn openat_2_hook(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    openat_hook(dirfd, path, flags, 0)
+}
+
+unsafe extern "C" fn fopen_hook(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    match resolve(path) {
+        None => libc::fopen(path, mode),
+        Some(FsRedirect::Path(new_path)) => libc::fopen(new_path.as_ptr(), mode),
+        Some(FsRedirect::Block) => {
+            set_errno(libc::ENOENT);
+            null_mut()
+        }
+        Some(FsRedirect::Virtual(contents)) => {
+            let fd = memfd(&contents);
+            if fd < 0 {
+                return null_mut();
+            }
+            let file = libc::fdopen(fd, mode);
+            if file.is_null() {
+                libc::close(fd);
+            }
+            file
+        }
+    }
+}
+
+unsafe extern "C" fn stat_hook(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_with(libc::stat, path, buf)
+}
+
+// Same rules as stat, only the call that reaches libc differs
+unsafe extern "C" fn lstat_hook(path: *const c_char, buf: *mut libc::stat) -> c_int {
+    stat_with(libc::lstat, path, buf)
+}
+
+unsafe fn stat_with(
+    stat: unsafe extern "C" fn(*const c_char, *mut libc::stat) -> c_int,
+    path: *const c_char,
+    buf: *mut libc::stat,
+) -> c_int {
+    match resolve(path) {
+        None => stat(path, buf),
+        Some(FsRedirect::Path(new_path)) => stat(new_path.as_ptr(), buf),
+        Some(FsRedirect::Block) => {
+            set_errno(libc::ENOENT);
+            -1
+        }
+        Some(FsRedirect::Virtual(contents)) => {
+            let fd = memfd(&contents);
+            if fd < 0 {
+                return -1;
+            }
+            let result = libc::fstat(fd, buf);
+            libc::close(fd);
+            result
+        }
+    }
+}
+
+unsafe extern "C" fn access_hook(path: *const c_char, mode: c_int) -> c_int {
+    match resolve(path) {
+        None => libc::access(path, mode),
+        Some(FsRedirect::Path(new_path)) => libc::access(new_path.as_ptr(), mode),
+        Some(FsRedirect::Block) => {
+            set_errno(libc::ENOENT);
+            -1
+        }
+        // Virtual files can always be read, never executed
+        Some(FsRedirect::Virtual(_)) if mode & libc::X_OK != 0 => {
+            set_errno(libc::EACCES);
+            -1
+        }
+        Some(FsRedirect::Virtual(_)) => 0,
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{self, FsRule, ModConfig},
+        test_fixture,
+    };
+
+    #[test]
+    fn prefixes_match_whole_components() {
+        assert!(matches_prefix(b"/a/b", b"/a/b"));
+        assert!(matches_prefix(b"/a/b/c", b"/a/b"));
+        assert!(matches_prefix(b"/a/b/c", b"/a/b/"));
+        assert!(!matches_prefix(b"/a/bc", b"/a/b"));
+        assert!(!matches_prefix(b"/a", b"/a/b"));
+    }
+
+    #[test]
+    fn rules_apply_to_a_hooked_library() {
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let rule = |prefix: &str, action: FsAction| FsRule {
+            prefix: prefix.to_string(),
+            action,
+        };
+        config::init_test_config(ModConfig {
+            fs_rules: vec![
+                rule("/xelo_test/blocked", FsAction::Block),
+                rule("/xelo_test/virtual", FsAction::Virtualize {
+                    contents: "hello".to_string(),
+                }),
+                rule("/xelo_test/crate", FsAction::Redirect {
+                    target: manifest_dir.to_string(),
+                }),
+            ],
+            ..ModConfig::default()
+        });
+
+        let fixture = test_fixture::load("fixture", "fs_hooks");
+        let lib = plt_rs::collect_modules()
+            .into_iter()
+            .find(|lib| lib.name().contains(fixture.soname.as_str()))
+            .unwrap();
+        let hooks = install(&DynamicLibrary::initialize(lib).unwrap());
+        for symbol in ["open", "openat", "fopen", "stat", "lstat", "access"] {
+            assert!(
+                hooks.report().contains(&(symbol, crate::plthook::SymbolHookState::Patched)),
+                "{symbol} was not patched"
+            );
+        }
+
+        type PathFn = unsafe extern "C" fn(*const c_char) -> c_int;
+        type FopenFn = unsafe extern "C" fn(*const c_char) -> *mut FILE;
+        let call = |symbol: &str, path: &std::ffi::CStr| unsafe {
+            std::mem::transmute::<usize, PathFn>(fixture.symbol(symbol))(path.as_ptr())
+        };
+        let fopen: FopenFn = unsafe { std::mem::transmute(fixture.symbol("fixture_fopen")) };
+
+        let real = CString::new(format!("{manifest_dir}/Cargo.toml")).unwrap();
+        for symbol in ["fixture_stat", "fixture_lstat", "fixture_access"] {
+            assert_eq!(call(symbol, real.as_c_str()), 0, "{symbol}");
+            assert_eq!(call(symbol, c"/xelo_test/blocked/file"), -1, "{symbol}");
+            assert_eq!(call(symbol, c"/xelo_test/virtual/file"), 0, "{symbol}");
+            assert_eq!(call(symbol, c"/xelo_test/crate/Cargo.toml"), 0, "{symbol}");
+            assert_eq!(call(symbol, c"/xelo_test/crates/Cargo.toml"), -1, "{symbol}");
+        }
+
+        for symbol in ["fixture_open", "fixture_openat"] {
+            assert_eq!(call(symbol, c"/xelo_test/blocked"), -1, "{symbol}");
+            let fd = call(symbol, c"/xelo_test/virtual/file");
+            assert!(fd >= 0, "{symbol}");
+            let mut contents = [0u8; 16];
+            let read = unsafe { libc::read(fd, contents.as_mut_ptr().cast(), contents.len()) };
+            assert_eq!(&contents[..read as usize], b"hello");
+            unsafe { libc::close(fd) };
+            let fd = call(symbol, c"/xelo_test/crate/Cargo.toml");
+            assert!(fd >= 0, "{symbol}");
+            unsafe { libc::close(fd) };
+        }
+
+        unsafe {
+            assert!(fopen(c"/xelo_test/blocked".as_ptr()).is_null());
+            let file = fopen(c"/xelo_test/virtual".as_ptr());
+            assert!(!file.is_null());
+            assert_eq!(libc::fgetc(file), b'h' as c_int);
+            libc::fclose(file);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::{materials, modules, status};
+use materialbin::MinecraftVersion;
+use ndk::asset::AssetManager;
+use ndk_sys::AAssetManager;
+use std::{collections::BTreeMap, ffi::CStr, fmt, io::Read, ptr::NonNull, sync::OnceLock};
+
+// What the running game is, pieced together from whatever sources work on
+// this build. The game version and the material format are separate since
+// several game versions share one format.
+static GAME_VERSION: OnceLock<GameVersion> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Semver {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    // Missing from the pack manifest, only the library has it
+    pub build: Option<u32>,
+}
+
+impl fmt::Display for Semver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(build) = self.build {
+            write!(f, ".{build:02}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Semver {
+    // Bedrock versions look like 1.21.51.02, the build part is optional
+    fn parse(text: &str) -> Option<Semver> {
+        let parts: Vec<&str> = text.split('.').collect();
+        if !(3..=4).contains(&parts.len()) {
+            return None;
+        }
+        let number = |part: &str| -> Option<u32> {
+            if part.is_empty() || part.len() > 4 || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            part.parse().ok()
+        };
+        let version = Semver {
+            major: number(parts[0])?,
+            minor: number(parts[1])?,
+            patch: number(parts[2])?,
+            build: match parts.get(3) {
+                Some(part) => Some(number(part)?),
+                None => None,
+            },
+        };
+        if version.major != 1 || version.minor < 16 {
+            return None;
+        }
+        Some(version)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameVersion {
+    pub semver: Option<Semver>,
+    pub material: Option<MinecraftVersion>,
+    // Where the semver came from, for the logs and status file
+    pub source: &'static str,
+}
+
+// Detects everything once, later calls just return the cached result
+pub fn detect(man: *mut AAssetManager) -> &'static GameVersion {
+    GAME_VERSION.get_or_init(|| {
+        let manager = NonNull::new(man).map(|pointer| unsafe { AssetManager::from_ptr(pointer) });
+        if manager.is_none() {
+            log::warn!("AssetManager is null?, preposterous, only the library can be checked");
+        }
+        // Scanning the library is slow and picks between look alike strings,
+        // so it only runs when the apk has nothing to say
+        let (semver, source) = if let Some(semver) = manager.as_ref().and_then(asset_version) {
+            (Some(semver), "version asset")
+        } else if let Some(semver) = manager.as_ref().and_then(manifest_version) {
+            (Some(semver), "vanilla manifest")
+        } else if let Some(semver) = library_version() {
+            (Some(semver), "libminecraftpe.so")
+        } else {
+            (None, "none")
+        };
+        let material = manager.as_ref().and_then(material_version);
+        match semver {
+            Some(semver) => log::info!("Game version is {semver} (from {source})"),
+            None => log::error!("Could not detect the game version"),
+        }
+        match material {
+            Some(material) => log::info!("Material format is {material}"),
+            None => log::error!("Shader fixing is disabled as no material format was found"),
+        }
+        let version = GameVersion {
+            semver,
+            material,
+            source,
+        };
+        status::set_game_version(&version);
+        version
+    })
+}
+
+// The version string sits in .rodata as its own NUL terminated string, the
+// most common one wins since a few dependency versions look alike
+fn library_version() -> Option<Semver> {
+    let module = modules::find_module("libminecraftpe.so")?;
+    let mut found: BTreeMap<Semver, usize> = BTreeMap::new();
+    for segment in module
+        .segments
+        .iter()
+        .filter(|s| s.readable && !s.writable && !s.executable)
+    {
+        let data = unsafe { core::slice::from_raw_parts(segment.start as *const u8, segment.size) };
+        for string in data.split(|&b| b == 0) {
+            if !(8..=16).contains(&string.len()) || !string.starts_with(b"1.") {
+                continue;
+            }
+            let semver = std::str::from_utf8(string).ok().and_then(Semver::parse);
+            if let Some(semver) = semver.filter(|semver| semver.build.is_some()) {
+                *found.entry(semver).or_default() += 1;
+            }
+        }
+    }
+    found
+        .into_iter()
+        .max_by_key(|(semver, count)| (*count, *semver))
+        .map(|(semver, _)| semver)
+}
+
+fn open_asset(man: &AssetManager, paths: &[&CStr]) -> Option<Vec<u8>> {
+    let mut asset = paths.iter().find_map(|path| man.open(path))?;
+    let mut buf = Vec::with_capacity(asset.length());
+    if let Err(e) = asset.read_to_end(&mut buf) {
+        log::error!("Something is wrong with AssetManager, version detection failed: {e}");
+        return None;
+    }
+    Some(buf)
+}
+
+// The apk carries the full version as plain text
+fn asset_version(man: &AssetManager) -> Option<Semver> {
+    let version = open_asset(man, &[c"assets/version", c"version"])?;
+    Semver::parse(std::str::from_utf8(&version).ok()?.trim())
+}
+
+// The vanilla pack's header version follows the game, minus the build number
+fn manifest_version(man: &AssetManager) -> Option<Semver> {
+    let manifest = open_asset(man, &[
+        c"assets/resource_packs/vanilla/manifest.json",
+        c"resource_packs/vanilla/manifest.json",
+    ])?;
+    let manifest: serde_json::Value = serde_json::from_slice(&manifest).ok()?;
+    let version = manifest.pointer("/header/version")?.as_array()?;
+    let part = |index: usize| version.get(index)?.as_u64().map(|n| n as u32);
+    Some(Semver {
+        major: part(0)?,
+        minor: part(1)?,
+        patch: part(2)?,
+        build: None,
+    })
+}
+
+// Any vanilla material works as a probe, a few are tried in case one moves
+fn material_version(man: &AssetManager) -> Option<MinecraftVersion> {
+    const PROBES: &[&str] = &["UIText", "RenderChunk", "Sky", "ActorBanner"];
+    for probe in PROBES {
+        for prefix in ["assets/renderer/materials/", "renderer/materials/"] {
+            let Ok(path) = std::ffi::CString::new(format!("{prefix}{probe}.material.bin")) else {
+                continue;
+            };
+            let Some(data) = open_asset(man, &[&path]) else {
+                continue;
+            };
+            if let Some((_, version)) = materials::parse(&data) {
+                return Some(version);
+            }
+            log::warn!("{probe}.material.bin matched no known material format");
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semver_takes_an_optional_build() {
+        let version = Semver::parse("1.21.51.02").unwrap();
+        assert_eq!((version.major, version.minor, version.patch, version.build), (1, 21, 51, Some(2)));
+        assert_eq!(version.to_string(), "1.21.51.02");
+        let version = Semver::parse("1.20.80").unwrap();
+        assert_eq!(version.build, None);
+        assert_eq!(version.to_string(), "1.20.80");
+    }
+
+    #[test]
+    fn semver_rejects_what_is_not_a_bedrock_version() {
+        for text in ["", "1.21", "1.21.51.02.1", "2.0.0", "1.15.0", "1.21.x", "1.21.-1", "1..0", "1.21.51.12345"] {
+            assert_eq!(Semver::parse(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// FNV-1a for our own cache keys and checksums, only ever used for lookups
+// and corruption checks, never for anything security related. Resource paths
+// use the game's own hash, see resource_location::hash_path.
+
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Share codes carry the 32 bit variant, changing it would break old codes
+pub fn fnv1a32(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
@@ -0,0 +1,91 @@
+use crate::{config::get_base_config, offset_cache, signatures, status, SimpleMapRange};
+use std::sync::Mutex;
+
+// Every inline hook on a game function goes here. The detour itself is
+// written with bhook::hook_fn! next to the code that uses it, the registry
+// only knows which signature it goes on and whether it got installed.
+macro_rules! inline_hooks {
+    ($($name:literal: $hook:path => $signature:expr),* $(,)?) => {
+        &[
+            $(InlineHook {
+                name: $name,
+                signature: $signature,
+                install: |addr| unsafe {
+                    $hook(addr);
+                },
+            }),*
+        ]
+    };
+}
+
+pub const RPM_CTOR_HOOK: &str = "rpm_ctor";
+
+static INLINE_HOOKS: &[InlineHook] = inline_hooks! {
+    "rpm_ctor": crate::rpm_ctor::hook_address => signatures::RPM_CTOR,
+};
+
+static STATES: Mutex<Vec<(&'static str, HookState)>> = Mutex::new(Vec::new());
+
+pub struct InlineHook {
+    pub name: &'static str,
+    // Name in the signature database
+    pub signature: &'static str,
+    install: fn(*mut u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookState {
+    Installed(usize),
+    // Turned off with disabled_hooks in the config, stays that way until the
+    // next launch
+    Disabled,
+    Failed(String),
+}
+
+// Resolves and installs every enabled hook, a hook failing only takes out
+// whatever depends on that one hook. Detours can't be taken out again, so
+// this runs once at startup.
+pub fn install_all(range: &SimpleMapRange, build_id: Option<&str>) {
+    let config = get_base_config();
+    let mut states = STATES.lock().unwrap();
+    for hook in INLINE_HOOKS {
+        let state = if config.disabled_hooks.iter().any(|name| name == hook.name) {
+            log::info!("Hook {} is disabled", hook.name);
+            HookState::Disabled
+        } else {
+            match offset_cache::resolve(hook.signature, range, build_id) {
+                Some(addr) => {
+                    log::info!("Installing hook {} at {:p}", hook.name, addr);
+                    (hook.install)(addr.cast_mut());
+                    HookState::Installed(addr as usize)
+                }
+                None => HookState::Failed(format!("No signature for {} was found", hook.signature)),
+            }
+        };
+        if let HookState::Failed(reason) = &state {
+            log::error!("Hook {} failed: {}", hook.name, reason);
+        }
+        status::set_inline_hook(hook.name, &state);
+        states.retain(|(name, _)| *name != hook.name);
+        states.push((hook.name, state));
+    }
+}
+
+pub fn state(name: &str) -> Option<HookState> {
+    let states = STATES.lock().unwrap();
+    states
+        .iter()
+        .find(|(hook, _)| *hook == name)
+        .map(|(_, state)| state.clone())
+}
+
+// Marks every hook as failed, for when the library could not even be scanned
+pub fn fail_all(reason: &str) {
+    let mut states = STATES.lock().unwrap();
+    for hook in INLINE_HOOKS {
+        let state = HookState::Failed(reason.to_string());
+        status::set_inline_hook(hook.name, &state);
+        states.retain(|(name, _)| *name != hook.name);
+        states.push((hook.name, state));
+    }
+}
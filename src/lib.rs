@@ -1,55 +1,41 @@
 use std::{
+    collections::BTreeSet,
     ffi::CStr,
-    fs,
-    pin::Pin,
     ptr::null_mut,
-    str::SplitWhitespace,
-    sync::{atomic::AtomicPtr, OnceLock},
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, Ordering},
+        Mutex, OnceLock,
+    },
 };
 mod config;
 use config::init_config;
 mod aasset;
+mod camera;
+mod elf;
+mod fs_hooks;
+mod game_version;
+mod hash;
+mod inline_hooks;
+mod material_cache;
+pub mod material_patch;
+pub mod materials;
+mod modules;
+mod offset_cache;
 mod plthook;
-use crate::plthook::replace_plt_functions;
+mod resource_location;
+pub use resource_location::{ResourceFileSystem, ResourceLocation};
+mod rpm;
+mod shader_platforms;
+mod signatures;
+mod status;
+#[cfg(all(test, target_os = "linux"))]
+mod test_fixture;
+use crate::plthook::{replace_plt_functions, PltHooks, SymbolHookState};
 use bhook::hook_fn;
 use core::mem::transmute;
-use cxx::CxxString;
 use libc::c_void;
 use plt_rs::DynamicLibrary;
-use tinypatscan::Pattern;
 
-#[cfg(target_arch = "aarch64")]
-const RPMC_PATTERNS: [Pattern<80>; 2] = [
-    Pattern::from_str("FF 03 03 D1 FD 7B 07 A9 FD C3 01 91 F9 43 00 F9 F8 5F 09 A9 F6 57 0A A9 F4 4F 0B A9 59 D0 3B D5 F6 03 03 2A 28 17 40 F9 F5 03 02 AA F3 03 00 AA A8 83 1F F8 28 10 40 F9"),
-    Pattern::from_str("FF 83 02 D1 FD 7B 06 A9 FD 83 01 91 F8 5F 07 A9 F6 57 08 A9 F4 4F 09 A9 58 D0 3B D5 F6 03 03 2A 08 17 40 F9 F5 03 02 AA F3 03 00 AA A8 83 1F F8 28 10 40 F9 28 01 00 B4"),
-];
-#[cfg(target_arch = "arm")]
-const RPMC_PATTERNS: [Pattern<80>; 1] = [Pattern::from_str(
-    "F0 B5 03 AF 2D E9 00 ?? ?? B0 05 46 ?? 48 98 46 92 46 78 44 00 68 00 68 ?? 90 08 69",
-)];
-#[cfg(target_arch = "x86_64")]
-const RPMC_PATTERNS: [Pattern<80>; 2] = [
-    Pattern::from_str("55 41 57 41 56 41 55 41 54 53 48 83 EC ? 41 89 CF 49 89 D6 48 89 FB 64 48 8B 04 25 28 00 00 00 48 89 44 24 ? 48 8B 7E"),
-    Pattern::from_str("55 41 57 41 56 53 48 83 EC ? 41 89 CF 49 89 D6 48 89 FB 64 48 8B 04 25 28 00 00 00 48 89 44 24 ? 48 8B 7E"),
-];
-
-#[repr(transparent)]
-pub struct ResourceLocation(*mut c_void);
-
-impl ResourceLocation {
-    pub fn from_str(str: &CStr) -> ResourceLocation {
-        unsafe { resource_location_init(str.as_ptr(), str.count_bytes()) }
-    }
-}
-impl Drop for ResourceLocation {
-    fn drop(&mut self) {
-        unsafe { resource_location_free(self.0) }
-    }
-}
-extern "C" {
-    fn resource_location_init(strptr: *const libc::c_char, size: libc::size_t) -> ResourceLocation;
-    fn resource_location_free(loc: *mut c_void);
-}
 pub fn setup_logging() {
     android_logger::init_once(
         android_logger::Config::default().with_max_level(log::LevelFilter::Trace),
@@ -57,87 +43,127 @@ pub fn setup_logging() {
 }
 #[ctor::ctor]
 fn main() {
+    // The host build is only there for tests and materialbin-tool, there is
+    // no game to hook
+    if !cfg!(target_os = "android") {
+        return;
+    }
     setup_logging();
     init_config();
     log::info!("Starting");
-    let mcmap = find_minecraft_library_manually()
-        .expect("Cannot find libminecraftpe.so in memory maps - device not supported");
-    let addr = find_signatures(&RPMC_PATTERNS, mcmap).expect("No signature was found");
-    log::info!("Hooking ResourcePackManager constructor");
-    unsafe {
-        rpm_ctor::hook_address(addr as *mut u8);
-    };
+    // Without the ResourcePackManager we can still do everything that does not
+    // need pack files, so a missing signature must not take the game down
+    match find_minecraft_library() {
+        Ok(mcmap) => {
+            let build_id = MC_BUILD_ID.get_or_init(|| unsafe { elf::build_id(mcmap.base(), mcmap.bias()) });
+            match build_id {
+                Some(id) => log::info!("libminecraftpe.so build-id: {id}"),
+                None => log::warn!("libminecraftpe.so has no build-id, offsets won't be cached"),
+            }
+            inline_hooks::install_all(&mcmap, build_id.as_deref());
+        }
+        Err(e) => {
+            log::error!("Cannot scan libminecraftpe.so: {e}");
+            inline_hooks::fail_all(&e.to_string());
+        }
+    }
+    match inline_hooks::state(inline_hooks::RPM_CTOR_HOOK) {
+        Some(inline_hooks::HookState::Installed(_)) => status::set_available(status::PACK_REDIRECTS),
+        state => {
+            let reason = match state {
+                Some(inline_hooks::HookState::Failed(reason)) => reason,
+                _ => "ResourcePackManager hook is disabled".to_string(),
+            };
+            log::error!("{reason}, continuing without resource pack redirects");
+            PACK_REDIRECTS_UNAVAILABLE.store(true, Ordering::Release);
+            status::set_unavailable(status::PACK_REDIRECTS, reason);
+        }
+    }
     log::info!("Hooking AssetManager functions");
-    hook_aaset();
+    match hook_aaset() {
+        Ok(()) => status::set_available(status::ASSET_HOOKS),
+        Err(e) => {
+            log::error!("Failed to hook AssetManager functions: {e}");
+            status::set_unavailable(status::ASSET_HOOKS, e.to_string());
+        }
+    }
 }
+
+static MC_BUILD_ID: OnceLock<Option<String>> = OnceLock::new();
+
+// NT_GNU_BUILD_ID of libminecraftpe.so, if it had one
+pub fn build_id() -> Option<&'static str> {
+    MC_BUILD_ID.get().and_then(|id| id.as_deref())
+}
+
+// Set when the ResourcePackManager could not be found, pack redirects are
+// skipped instead of waiting for a constructor that will never be hooked
+pub static PACK_REDIRECTS_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug)]
-struct SimpleMapRange {
+pub(crate) struct SimpleMapRange {
     start: usize,
     size: usize,
+    // Where the ELF header is mapped, offsets we keep around are relative to this
+    base: usize,
+    // What gets added to every vaddr in the file
+    bias: usize,
 }
 
 impl SimpleMapRange {
-    fn start(&self) -> usize {
+    pub(crate) fn start(&self) -> usize {
         self.start
     }
 
-    fn size(&self) -> usize {
+    pub(crate) fn size(&self) -> usize {
         self.size
     }
-}
 
-fn find_minecraft_library_manually() -> Result<SimpleMapRange, Box<dyn std::error::Error>> {
-    let contents = fs::read_to_string("/proc/self/maps")?;
-    for line in contents.lines() {
-        if line.trim().is_empty() {
-            continue;
-        }
-        let Some((addr_start, addr_end)) = parse_range(line.split_whitespace()) else {
-            continue;
-        };
-        let start = usize::from_str_radix(addr_start, 16)?;
-        let end = usize::from_str_radix(addr_end, 16)?;
-        log::info!("Found libminecraftpe.so at: {:x}-{:x}", start, end);
-        return Ok(SimpleMapRange {
-            start,
-            size: end - start,
-        });
+    pub(crate) fn base(&self) -> usize {
+        self.base
     }
 
-    Err("libminecraftpe.so not found in memory maps".into())
-}
-fn parse_range(mut line: SplitWhitespace) -> Option<(&str, &str)> {
-    let addr_range = line.next()?;
-    let perms = line.next()?;
-    let pathname = line.last()?;
-    if perms.contains('x') && pathname.ends_with("libminecraftpe.so") {
-        return addr_range.split_once('-');
+    pub(crate) fn bias(&self) -> usize {
+        self.bias
     }
-    None
-}
 
-fn find_signatures(signatures: &[Pattern<80>], range: SimpleMapRange) -> Option<*const u8> {
-    for sig in signatures {
-        let libbytes =
-            unsafe { core::slice::from_raw_parts(range.start() as *const u8, range.size()) };
+    pub(crate) fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr - self.start < self.size
+    }
+}
 
-        let addr = if cfg!(target_arch = "arm") {
-            sig.search(libbytes)
-        } else {
-            sig.simd_search(libbytes)
-        };
-        let addr = match addr {
-            Some(val) => libbytes[val..].as_ptr(),
-            None => {
-                log::error!("Cannot find signature");
-                continue;
-            }
-        };
-        #[cfg(target_arch = "arm")]
-        let addr = unsafe { addr.offset(1) };
-        return Some(addr);
+fn find_minecraft_library() -> Result<SimpleMapRange, Box<dyn std::error::Error>> {
+    let module = modules::find_module("libminecraftpe.so")
+        .ok_or("libminecraftpe.so is not loaded")?;
+    log::info!(
+        "Found {} at base {:x}, bias {:x}",
+        module.path,
+        module.base,
+        module.bias
+    );
+    for segment in &module.segments {
+        log::debug!(
+            "Segment {:x}-{:x} {}{}{}",
+            segment.start,
+            segment.start + segment.size,
+            if segment.readable { 'r' } else { '-' },
+            if segment.writable { 'w' } else { '-' },
+            if segment.executable { 'x' } else { '-' },
+        );
     }
-    None
+    if module.text.is_none() {
+        log::info!("No .text section info, scanning the executable segments");
+    }
+    let (start, size) = module
+        .code_range()
+        .ok_or("libminecraftpe.so has no executable segments")?;
+    log::info!("Scanning libminecraftpe.so code at: {:x}-{:x}", start, start + size);
+    Ok(SimpleMapRange {
+        start,
+        size,
+        base: module.base,
+        bias: module.bias,
+    })
 }
 
 macro_rules! cast_array {
@@ -149,9 +175,27 @@ macro_rules! cast_array {
         ]
     }
 }
-pub fn hook_aaset() {
-    let lib_entry = find_lib("libminecraftpe").expect("Cannot find minecraftpe");
-    let dyn_lib = DynamicLibrary::initialize(lib_entry).expect("Failed to find mc info");
+pub fn hook_aaset() -> Result<(), Box<dyn std::error::Error>> {
+    let lib_entry = find_lib("libminecraftpe").ok_or("Cannot find minecraftpe")?;
+    let dyn_lib = DynamicLibrary::initialize(lib_entry)?;
+    let mut hooks = hook_aaset_in(&dyn_lib);
+    if let Err(e) = check_aaset_hooks(&hooks) {
+        hooks.unhook();
+        return Err(e.into());
+    }
+    AASSET_HOOKS.lock().unwrap().push(hooks);
+    hook_dlopen();
+    hook_extra_libraries();
+    if config::get_config().fs_hooks {
+        log::info!("Hooking libc file functions");
+        let fs_hooks = fs_hooks::install(&dyn_lib);
+        status::set_hooks(&fs_hooks);
+        *FS_HOOKS.lock().unwrap() = Some(fs_hooks);
+    }
+    Ok(())
+}
+
+fn hook_aaset_in(dyn_lib: &DynamicLibrary) -> PltHooks {
     let asset_fn_list = cast_array! {
         "AAssetManager_open" -> aasset::open,
         "AAsset_read" -> aasset::read,
@@ -167,33 +211,280 @@ pub fn hook_aaset() {
         "AAsset_getBuffer" -> aasset::get_buffer,
         "AAsset_isAllocated" -> aasset::is_alloc,
     };
-    replace_plt_functions(&dyn_lib, asset_fn_list);
+    let hooks = replace_plt_functions(dyn_lib, asset_fn_list);
+    status::set_hooks(&hooks);
+    let unpatched: Vec<_> = hooks.unpatched().collect();
+    if !unpatched.is_empty() {
+        log::warn!(
+            "AssetManager functions not hooked in {}: {}",
+            hooks.library(),
+            unpatched.join(", ")
+        );
+    }
+    hooks
+}
+
+// Half of the AAsset calls going through us and half not would mix our
+// assets with the game's, so anything short of this gets rolled back.
+// Symbols a library doesn't import are fine, it never calls them.
+fn check_aaset_hooks(hooks: &PltHooks) -> Result<(), String> {
+    let state = |symbol: &str| {
+        hooks
+            .report()
+            .iter()
+            .find(|(name, _)| *name == symbol)
+            .map(|(_, state)| *state)
+    };
+    for required in ["AAssetManager_open", "AAsset_read"] {
+        if state(required) != Some(SymbolHookState::Patched) {
+            return Err(format!("{required} is not hooked in {}", hooks.library()));
+        }
+    }
+    let failed: Vec<_> = hooks
+        .report()
+        .iter()
+        .filter(|(_, state)| *state == SymbolHookState::Failed)
+        .map(|(symbol, _)| *symbol)
+        .collect();
+    if !failed.is_empty() {
+        return Err(format!("Failed to hook {} in {}", failed.join(", "), hooks.library()));
+    }
+    Ok(())
+}
+
+// Hooks the configured libraries (or everything importing AAsset functions)
+// that aren't hooked yet, safe to call again whenever new libraries show up
+fn hook_extra_libraries() {
+    let config = config::get_config();
+    if config.asset_hook_libraries.is_empty() && !config.asset_hook_all_modules {
+        return;
+    }
+    let own_name = own_library_name();
+    let mut hooked = AASSET_HOOKS.lock().unwrap();
+    let mut rejected = NO_AASSET_LIBRARIES.lock().unwrap();
+    for lib in plt_rs::collect_modules() {
+        let name = lib.name().to_string();
+        // Patching ourselves would make every fallback call loop back into us
+        if own_name.as_deref() == Some(name.as_str())
+            || hooked.iter().any(|hooks| hooks.library() == name)
+        {
+            continue;
+        }
+        let wanted = config
+            .asset_hook_libraries
+            .iter()
+            .any(|target| name.contains(target.as_str()));
+        if !wanted && !config.asset_hook_all_modules {
+            continue;
+        }
+        // Reading the dynamic section again for every dlopen adds up in the
+        // all modules mode, what didn't import AAsset before still won't
+        let key = (name, lib.addr());
+        if !wanted && rejected.contains(&key) {
+            continue;
+        }
+        let name = key.0.as_str();
+        let dyn_lib = match DynamicLibrary::initialize(lib) {
+            Ok(lib) => lib,
+            Err(e) => {
+                if wanted {
+                    log::warn!("Failed to read {name}: {e}");
+                }
+                rejected.insert(key);
+                continue;
+            }
+        };
+        if !wanted && dyn_lib.try_find_function("AAssetManager_open").is_none() {
+            rejected.insert(key);
+            continue;
+        }
+        let mut hooks = hook_aaset_in(&dyn_lib);
+        match check_aaset_hooks(&hooks) {
+            Ok(()) => {
+                log::info!("Hooked AssetManager functions in {name}");
+                hooked.push(hooks);
+            }
+            Err(e) => {
+                log::info!("Skipping {name}: {e}");
+                hooks.unhook();
+                rejected.insert(key);
+            }
+        }
+    }
+}
+
+fn own_library_name() -> Option<String> {
+    let mut info: libc::Dl_info = unsafe { core::mem::zeroed() };
+    let found = unsafe { libc::dladdr(hook_aaset as *const c_void, &mut info) };
+    if found == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(info.dli_fname) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+// Libraries dlopen'd after startup need their hooks too. Every dlopen in
+// the process ends up in the linker's __loader_* functions, which take the
+// caller explicitly so hooking them doesn't change the namespace a library
+// gets loaded into. Linkers that don't export them get the dlopen imports of
+// every library patched instead.
+fn hook_dlopen() {
+    if hook_loader() {
+        return;
+    }
+    log::warn!("Linker does not export __loader_dlopen, hooking dlopen imports instead");
+    hook_dlopen_imports();
+}
+
+fn hook_loader() -> bool {
+    let dlopen = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__loader_dlopen".as_ptr()) };
+    let dlopen_ext = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__loader_android_dlopen_ext".as_ptr()) };
+    if dlopen.is_null() || dlopen_ext.is_null() {
+        return false;
+    }
+    log::info!("Hooking __loader_dlopen at {dlopen:p} and __loader_android_dlopen_ext at {dlopen_ext:p}");
+    unsafe {
+        loader_dlopen::hook_address(dlopen.cast());
+        loader_android_dlopen_ext::hook_address(dlopen_ext.cast());
+    }
+    true
+}
+
+// Patches dlopen and android_dlopen_ext in every library that doesn't have
+// them patched yet, called again after each dlopen to catch new libraries
+fn hook_dlopen_imports() {
+    let own_name = own_library_name();
+    let mut hooked = DLOPEN_HOOKS.lock().unwrap();
+    let mut rejected = NO_DLOPEN_LIBRARIES.lock().unwrap();
+    for lib in plt_rs::collect_modules() {
+        let name = lib.name().to_string();
+        if own_name.as_deref() == Some(name.as_str())
+            || hooked.iter().any(|hooks| hooks.library() == name)
+        {
+            continue;
+        }
+        let key = (name, lib.addr());
+        if rejected.contains(&key) {
+            continue;
+        }
+        let Ok(dyn_lib) = DynamicLibrary::initialize(lib) else {
+            rejected.insert(key);
+            continue;
+        };
+        if dyn_lib.try_find_function("dlopen").is_none()
+            && dyn_lib.try_find_function("android_dlopen_ext").is_none()
+        {
+            rejected.insert(key);
+            continue;
+        }
+        let dlopen_fn_list = cast_array! {
+            "dlopen" -> dlopen_hook,
+            "android_dlopen_ext" -> android_dlopen_ext_hook,
+        };
+        let hooks = replace_plt_functions(&dyn_lib, dlopen_fn_list);
+        status::set_hooks(&hooks);
+        // Every library got its slot from the same libdl, any one will do
+        let _ = ORIGINAL_DLOPEN.compare_exchange(
+            null_mut(),
+            hooks.original("dlopen").cast_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        let _ = ORIGINAL_DLOPEN_EXT.compare_exchange(
+            null_mut(),
+            hooks.original("android_dlopen_ext").cast_mut(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+        hooked.push(hooks);
+    }
+}
+
+fn on_library_loaded() {
+    hook_extra_libraries();
+    if !DLOPEN_HOOKS.lock().unwrap().is_empty() {
+        hook_dlopen_imports();
+    }
+}
+
+hook_fn! {
+    fn loader_dlopen(filename: *const libc::c_char, flags: libc::c_int, caller: *const c_void) -> *mut c_void = {
+        let handle = call_original(filename, flags, caller);
+        if !handle.is_null() {
+            on_library_loaded();
+        }
+        handle
+    }
+}
+
+hook_fn! {
+    fn loader_android_dlopen_ext(filename: *const libc::c_char, flags: libc::c_int, extinfo: *const c_void, caller: *const c_void) -> *mut c_void = {
+        let handle = call_original(filename, flags, extinfo, caller);
+        if !handle.is_null() {
+            on_library_loaded();
+        }
+        handle
+    }
+}
+
+unsafe extern "C" fn dlopen_hook(filename: *const libc::c_char, flags: libc::c_int) -> *mut c_void {
+    type DlopenFn = unsafe extern "C" fn(*const libc::c_char, libc::c_int) -> *mut c_void;
+    let original = ORIGINAL_DLOPEN.load(Ordering::Acquire);
+    let handle = if original.is_null() {
+        libc::dlopen(filename, flags)
+    } else {
+        transmute::<*mut u8, DlopenFn>(original)(filename, flags)
+    };
+    if !handle.is_null() {
+        on_library_loaded();
+    }
+    handle
+}
+
+unsafe extern "C" fn android_dlopen_ext_hook(
+    filename: *const libc::c_char,
+    flags: libc::c_int,
+    extinfo: *const c_void,
+) -> *mut c_void {
+    type DlopenExtFn =
+        unsafe extern "C" fn(*const libc::c_char, libc::c_int, *const c_void) -> *mut c_void;
+    let mut original = ORIGINAL_DLOPEN_EXT.load(Ordering::Acquire);
+    if original.is_null() {
+        // Only possible in the moment between patching and saving the original
+        original = libc::dlsym(libc::RTLD_DEFAULT, c"android_dlopen_ext".as_ptr()).cast();
+        if original.is_null() {
+            return null_mut();
+        }
+    }
+    let handle = transmute::<*mut u8, DlopenExtFn>(original)(filename, flags, extinfo);
+    if !handle.is_null() {
+        on_library_loaded();
+    }
+    handle
 }
+
+// Kept so the hooks can be inspected or taken out again later
+pub static AASSET_HOOKS: Mutex<Vec<PltHooks>> = Mutex::new(Vec::new());
+static DLOPEN_HOOKS: Mutex<Vec<PltHooks>> = Mutex::new(Vec::new());
+// Libraries, by name and load address, known to have nothing worth hooking
+static NO_AASSET_LIBRARIES: Mutex<BTreeSet<(String, usize)>> = Mutex::new(BTreeSet::new());
+static NO_DLOPEN_LIBRARIES: Mutex<BTreeSet<(String, usize)>> = Mutex::new(BTreeSet::new());
+static FS_HOOKS: Mutex<Option<PltHooks>> = Mutex::new(None);
+static ORIGINAL_DLOPEN: AtomicPtr<u8> = AtomicPtr::new(null_mut());
+static ORIGINAL_DLOPEN_EXT: AtomicPtr<u8> = AtomicPtr::new(null_mut());
 fn find_lib<'a>(target_name: &str) -> Option<plt_rs::LoadedLibrary<'a>> {
     let loaded_modules = plt_rs::collect_modules();
     loaded_modules
         .into_iter()
         .find(|lib| lib.name().contains(target_name))
 }
-pub static PACKM_OBJ: AtomicPtr<libc::c_void> = AtomicPtr::new(null_mut());
-pub static RPM_LOAD: OnceLock<RpmLoadFn> = OnceLock::new();
-
 hook_fn! {
     fn rpm_ctor(this: *mut libc::c_void,unk1: usize,unk2: usize,needs_init: bool) -> *mut libc::c_void = {
-        use std::sync::atomic::Ordering;
         log::info!("rpm ctor called");
         let result = call_original(this, unk1, unk2, needs_init);
         log::info!("RPM pointer has been obtained");
-        crate::PACKM_OBJ.store(this, Ordering::Release);
-        crate::RPM_LOAD.set(crate::get_load(this)).unwrap();
-        self_disable();
+        crate::rpm::register(this);
         log::info!("hook exit");
         result
     }
 }
-
-type RpmLoadFn = unsafe extern "C" fn(*mut c_void, ResourceLocation, Pin<&mut CxxString>) -> bool;
-unsafe fn get_load(packm_ptr: *mut c_void) -> RpmLoadFn {
-    let vptr = *transmute::<*mut c_void, *mut *mut *const u8>(packm_ptr);
-    transmute::<*const u8, RpmLoadFn>(*vptr.offset(2))
-}
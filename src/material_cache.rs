@@ -0,0 +1,167 @@
+use crate::{
+    config::{get_config, write_atomic},
+    hash,
+};
+use materialbin::MinecraftVersion;
+use std::{
+    fs::{self, File},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Once},
+    time::SystemTime,
+};
+
+// Converted material.bin files, so big shader packs only get converted once
+// per game version instead of on every launch and resource reload
+const CACHE_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/cache/materials";
+// Creating this file wipes the cache on the next launch
+const CLEAR_TRIGGER: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/cache/clear_materials";
+
+static CLEAR_CHECK: Once = Once::new();
+
+// Read only mapping of a cache file
+pub struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    fn open(path: &Path) -> io::Result<MappedFile> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty cache file"));
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MappedFile { ptr, len })
+    }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+// `tag` covers any other setting that changes the output
+fn cache_path(input: &[u8], version: MinecraftVersion, tag: &str) -> PathBuf {
+    let hash = hash::fnv1a64(input);
+    let version: String = format!("{version:?}")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '_' })
+        .collect();
+    Path::new(CACHE_DIR).join(format!(
+        "{hash:016x}-{}-{version}-{tag}-{}.bin",
+        input.len(),
+        env!("CARGO_PKG_VERSION")
+    ))
+}
+
+fn prepare() -> bool {
+    if !get_config().material_cache {
+        return false;
+    }
+    CLEAR_CHECK.call_once(|| {
+        if Path::new(CLEAR_TRIGGER).exists() {
+            log::info!("Clearing material cache");
+            if let Err(e) = fs::remove_dir_all(CACHE_DIR) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Failed to clear material cache: {e}");
+                }
+            }
+            let _ = fs::remove_file(CLEAR_TRIGGER);
+        }
+        if let Err(e) = fs::create_dir_all(CACHE_DIR) {
+            log::warn!("Failed to create material cache directory: {e}");
+        }
+    });
+    true
+}
+
+pub fn get(input: &[u8], version: MinecraftVersion, tag: &str) -> Option<Arc<MappedFile>> {
+    if !prepare() {
+        return None;
+    }
+    let path = cache_path(input, version, tag);
+    let mapped = match MappedFile::open(&path) {
+        Ok(mapped) => mapped,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            log::warn!("Dropping broken cache entry {}: {e}", path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+    };
+    // mtime doubles as the last use time for eviction
+    if let Ok(file) = File::options().append(true).open(&path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+    log::trace!("Material cache hit {}", path.display());
+    Some(Arc::new(mapped))
+}
+
+pub fn put(input: &[u8], version: MinecraftVersion, tag: &str, output: &[u8]) {
+    if !prepare() {
+        return;
+    }
+    let path = cache_path(input, version, tag);
+    let Some(path_str) = path.to_str() else {
+        return;
+    };
+    if let Err(e) = write_atomic(path_str, output) {
+        log::warn!("Failed to cache material: {e}");
+        return;
+    }
+    evict();
+}
+
+// Drops the least recently used entries until the cache fits the size cap
+fn evict() {
+    let max_size = get_config().material_cache_max_mb.saturating_mul(1024 * 1024);
+    let Ok(dir) = fs::read_dir(CACHE_DIR) else {
+        return;
+    };
+    let mut entries: Vec<(SystemTime, u64, PathBuf)> = dir
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let meta = entry.metadata().ok()?;
+            meta.is_file()
+                .then(|| (meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    if total <= max_size {
+        return;
+    }
+    entries.sort_by_key(|(modified, _, _)| *modified);
+    for (_, size, path) in entries {
+        if total <= max_size {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            log::info!("Evicted {} from material cache", path.display());
+            total -= size;
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{config::CONFIG_DIR, hash, status};
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scroll::{Pread, LE};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Edits on top of whatever material the game ships, so a feature survives
+// Mojang changing the rest of the shader. Every *.json in material_patches/
+// is one MaterialPatch.
+static PATCHES: Lazy<Vec<MaterialPatch>> = Lazy::new(load_patches);
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MaterialPatch {
+    // Material name without .material.bin, like RenderChunk
+    pub material: String,
+    // Passes to edit, every pass when empty
+    #[serde(default)]
+    pub passes: Vec<String>,
+    // Name to value, an empty value defines it without one
+    #[serde(default)]
+    pub add_defines: BTreeMap<String, String>,
+    #[serde(default)]
+    pub remove_defines: Vec<String>,
+    // Uniform name to a GLSL constant expression. The declaration becomes a
+    // const, so whatever the game uploads for it is ignored from then on.
+    #[serde(default, alias = "uniforms")]
+    pub pin_uniforms: BTreeMap<String, String>,
+    #[serde(default)]
+    pub stages: Vec<StageSwap>,
+    // Applied last, in order
+    #[serde(default)]
+    pub replacements: Vec<SourceReplacement>,
+    // File name, used in the status report
+    #[serde(skip)]
+    name: String,
+    // Hash of the patch and its stage sources, part of the cache key
+    #[serde(skip)]
+    fingerprint: u64,
+}
+
+// Replaces the source of one shader stage with a file next to the patch
+#[derive(Deserialize, Debug, Clone)]
+pub struct StageSwap {
+    pub pass: String,
+    // Vertex, Fragment or Compute
+    pub stage: String,
+    // Like ESSL_310, every text platform when missing
+    #[serde(default)]
+    pub platform: Option<String>,
+    pub source: String,
+    #[serde(skip)]
+    contents: String,
+}
+
+// Text edit on the shader source. One that matches nowhere in the material
+// fails the whole patch, since it most likely means the game changed.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SourceReplacement {
+    pub find: String,
+    pub replace: String,
+    #[serde(default)]
+    pub mode: ReplaceMode,
+    #[serde(skip)]
+    regex: Option<Regex>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceMode {
+    // Exact text anywhere in the source
+    #[default]
+    Literal,
+    // Whole lines equal to `find`, ignoring surrounding whitespace
+    Line,
+    // `find` is a regex, `replace` can use $1 style groups
+    Regex,
+}
+
+impl SourceReplacement {
+    // Returns the edited source and how many places matched
+    fn apply(&self, source: &str) -> (String, usize) {
+        match self.mode {
+            ReplaceMode::Literal => {
+                let count = source.matches(self.find.as_str()).count();
+                (source.replace(self.find.as_str(), &self.replace), count)
+            }
+            ReplaceMode::Line => {
+                let mut count = 0;
+                let edited = source
+                    .split_inclusive('\n')
+                    .map(|line| {
+                        if line.trim() != self.find.trim() {
+                            return line.to_string();
+                        }
+                        count += 1;
+                        let ending = if line.ends_with('\n') { "\n" } else { "" };
+                        format!("{}{ending}", self.replace)
+                    })
+                    .collect();
+                (edited, count)
+            }
+            ReplaceMode::Regex => {
+                let Some(regex) = &self.regex else {
+                    return (source.to_string(), 0);
+                };
+                let count = regex.find_iter(source).count();
+                (regex.replace_all(source, self.replace.as_str()).into_owned(), count)
+            }
+        }
+    }
+}
+
+fn patches_dir() -> PathBuf {
+    PathBuf::from(CONFIG_DIR).join("material_patches")
+}
+
+fn load_patches() -> Vec<MaterialPatch> {
+    let dir = patches_dir();
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    let mut patches = Vec::new();
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match load_patch(&dir, &path) {
+            Ok(mut patch) => {
+                log::info!("Loaded material patch {name} for {}", patch.material);
+                patch.name = name;
+                patches.push(patch);
+            }
+            Err(e) => {
+                log::error!("Bad material patch {name}: {e}");
+                status::set_material_patch(&name, Some(e));
+            }
+        }
+    }
+    patches
+}
+
+pub fn load_patch(dir: &Path, path: &Path) -> Result<MaterialPatch, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut patch: MaterialPatch = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+    let mut hashed = text.into_bytes();
+    for swap in &mut patch.stages {
+        swap.contents = fs::read_to_string(dir.join(&swap.source))
+            .map_err(|e| format!("Can't read {}: {e}", swap.source))?;
+        hashed.extend_from_slice(swap.contents.as_bytes());
+    }
+    patch.fingerprint = hash::fnv1a64(&hashed);
+    for replacement in &mut patch.replacements {
+        if replacement.mode == ReplaceMode::Regex {
+            let regex = Regex::new(&replacement.find)
+                .map_err(|e| format!("Bad regex {}: {e}", replacement.find))?;
+            replacement.regex = Some(regex);
+        }
+    }
+    Ok(patch)
+}
+
+fn patches_for(material: &str) -> impl Iterator<Item = &'static MaterialPatch> + '_ {
+    PATCHES.iter().filter(move |patch| patch.material == material)
+}
+
+pub fn has_patches(material: &str) -> bool {
+    patches_for(material).next().is_some()
+}
+
+// Material cache tag for `material`, changes whenever one of its patches does
+pub fn cache_tag(material: &str) -> String {
+    let fingerprints: Vec<u8> = patches_for(material)
+        .flat_map(|patch| patch.fingerprint.to_le_bytes())
+        .collect();
+    format!("patch{:016x}", hash::fnv1a64(&fingerprints))
+}
+
+pub struct Patched {
+    pub data: Vec<u8>,
+    // False when some patch failed or changed nothing, such output must not
+    // be cached or that patch would never be reported again
+    pub complete: bool,
+}
+
+// Applies every patch for `material` to a material.bin of `version`, None
+// when nothing applied or it could not be parsed
+pub fn patch(material: &str, data: &[u8], version: MinecraftVersion) -> Option<Patched> {
+    if !has_patches(material) {
+        return None;
+    }
+    let mut definition: CompiledMaterialDefinition = match data.pread_with(0, version) {
+        Ok(definition) => definition,
+        Err(e) => {
+            log::error!("Can't patch {material}, parsing failed: {e}");
+            return None;
+        }
+    };
+    let mut edited = 0;
+    let mut complete = true;
+    for patch in patches_for(material) {
+        match apply(patch, &mut definition) {
+            Ok(0) => {
+                log::warn!("Material patch {} did not change anything", patch.name);
+                status::set_material_patch(&patch.name, Some("Nothing changed".to_string()));
+                complete = false;
+            }
+            Ok(count) => {
+                status::set_material_patch(&patch.name, None);
+                edited += count;
+            }
+            Err(e) => {
+                log::error!("Material patch {} failed: {e}", patch.name);
+                status::set_material_patch(&patch.name, Some(e));
+                complete = false;
+            }
+        }
+    }
+    if edited == 0 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(data.len());
+    if let Err(e) = definition.write(&mut output, version) {
+        log::error!("Failed to write patched {material}: {e}");
+        return None;
+    }
+    log::info!("Patched {edited} shaders in {material}");
+    Some(Patched {
+        data: output,
+        complete,
+    })
+}
+
+// Calls `f` with the pass, stage, platform and bgfx blob of every text shader
+// the patch targets, always in the same order
+fn for_each_shader(
+    patch: &MaterialPatch,
+    definition: &mut CompiledMaterialDefinition,
+    mut f: impl FnMut(&str, &str, &str, &mut Vec<u8>),
+) {
+    for (pass_name, pass) in definition.passes.iter_mut() {
+        if !patch.passes.is_empty() && !patch.passes.contains(pass_name) {
+            continue;
+        }
+        for variant in pass.variants.iter_mut() {
+            for (stage, code) in variant.shader_codes.iter_mut() {
+                if is_text_platform(&stage.platform_name) {
+                    f(pass_name, &stage.stage_name, &stage.platform_name, &mut code.bgfx_shader_data);
+                }
+            }
+        }
+    }
+}
+
+// Returns how many shaders were changed. Everything is edited on the side
+// first so a failing patch leaves the material untouched.
+pub fn apply(patch: &MaterialPatch, definition: &mut CompiledMaterialDefinition) -> Result<usize, String> {
+    let mut matches = vec![0; patch.replacements.len()];
+    let mut edits: Vec<Option<Vec<u8>>> = Vec::new();
+    for_each_shader(patch, definition, |pass_name, stage, platform, blob| {
+        let swap = patch.stages.iter().find(|swap| {
+            swap.pass == pass_name && swap.stage == stage && swap.platform.iter().all(|p| p == platform)
+        });
+        let result = edit_source(blob, |source| {
+            let mut source = match swap {
+                Some(swap) => swap.contents.clone(),
+                None => source.to_string(),
+            };
+            for name in &patch.remove_defines {
+                source = remove_define(&source, name);
+            }
+            for (name, value) in &patch.add_defines {
+                source = add_define(&source, name, value);
+            }
+            for (name, value) in &patch.pin_uniforms {
+                source = pin_uniform(&source, name, value);
+            }
+            for (replacement, count) in patch.replacements.iter().zip(matches.iter_mut()) {
+                let (edited, found) = replacement.apply(&source);
+                *count += found;
+                source = edited;
+            }
+            source
+        });
+        if result.is_none() {
+            log::warn!("Could not read {platform} {stage} shader of pass {pass_name}");
+        }
+        edits.push(result.filter(|data| *data != *blob));
+    });
+    if let Some((replacement, _)) = patch
+        .replacements
+        .iter()
+        .zip(&matches)
+        .find(|(_, count)| **count == 0)
+    {
+        return Err(format!("{:?} matched nothing", replacement.find));
+    }
+    let mut edits = edits.into_iter();
+    let mut edited = 0;
+    for_each_shader(patch, definition, |_, _, _, blob| {
+        if let Some(Some(data)) = edits.next() {
+            *blob = data;
+            edited += 1;
+        }
+    });
+    Ok(edited)
+}
+
+// Only GLSL and ESSL keep their source around, the rest is bytecode
+pub fn is_text_platform(platform: &str) -> bool {
+    platform.starts_with("ESSL") || platform.starts_with("GLSL")
+}
+
+// bgfx shader blob: magic, hashes, uniform table, then the length prefixed
+// code. Returns the uniform names and where the length and code sit.
+fn parse_blob(blob: &[u8]) -> Option<(Vec<String>, usize, std::ops::Range<usize>)> {
+    let magic_version = *blob.get(3)?;
+    let mut at = 12;
+    let uniform_count: u16 = blob.pread_with(at, LE).ok()?;
+    at += 2;
+    let mut uniforms = Vec::with_capacity(uniform_count as usize);
+    for _ in 0..uniform_count {
+        let name_len = *blob.get(at)? as usize;
+        let name = blob.get(at + 1..at + 1 + name_len)?;
+        uniforms.push(String::from_utf8_lossy(name).into_owned());
+        // name, type, num, reg index and reg count
+        at += 1 + name_len + 1 + 1 + 2 + 2;
+        if magic_version >= 8 {
+            at += 2;
+        }
+        if magic_version >= 10 {
+            at += 2;
+        }
+    }
+    let code_len: u32 = blob.pread_with(at, LE).ok()?;
+    let code_start = at + 4;
+    let code_end = code_start.checked_add(code_len as usize)?;
+    blob.get(code_start..code_end)?;
+    Some((uniforms, at, code_start..code_end))
+}
+
+pub fn shader_uniforms(blob: &[u8]) -> Vec<String> {
+    parse_blob(blob).map(|(uniforms, _, _)| uniforms).unwrap_or_default()
+}
+
+// Only there for text platforms
+pub fn shader_source(blob: &[u8]) -> Option<&str> {
+    let (_, _, code) = parse_blob(blob)?;
+    std::str::from_utf8(&blob[code]).ok()
+}
+
+pub fn shader_defines(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line.trim_start().split_whitespace();
+            (tokens.next() == Some("#define")).then(|| tokens.collect::<Vec<_>>().join(" "))
+        })
+        .collect()
+}
+
+// Everything after the code is kept as is
+fn edit_source(blob: &[u8], edit: impl FnOnce(&str) -> String) -> Option<Vec<u8>> {
+    let (_, len_at, code) = parse_blob(blob)?;
+    let source = std::str::from_utf8(&blob[code.clone()]).ok()?;
+    let new_source = edit(source);
+    let mut output = Vec::with_capacity(blob.len() + new_source.len());
+    output.extend_from_slice(&blob[..len_at]);
+    output.extend_from_slice(&(new_source.len() as u32).to_le_bytes());
+    output.extend_from_slice(new_source.as_bytes());
+    output.extend_from_slice(&blob[code.end..]);
+    Some(output)
+}
+
+fn is_define_of(line: &str, name: &str) -> bool {
+    let mut tokens = line.trim_start().split_whitespace();
+    tokens.next() == Some("#define") && tokens.next().is_some_and(|token| {
+        token == name || token.strip_prefix(name).is_some_and(|rest| rest.starts_with('('))
+    })
+}
+
+fn remove_define(source: &str, name: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .filter(|line| !is_define_of(line, name))
+        .collect()
+}
+
+// Goes right after #version since nothing is allowed before it
+fn add_define(source: &str, name: &str, value: &str) -> String {
+    let source = remove_define(source, name);
+    let define = format!("#define {name} {value}\n");
+    let insert_at = if source.trim_start().starts_with("#version") {
+        let version_start = source.find("#version").unwrap_or(0);
+        source[version_start..]
+            .find('\n')
+            .map_or(source.len(), |end| version_start + end + 1)
+    } else {
+        0
+    };
+    let mut output = String::with_capacity(source.len() + define.len());
+    output.push_str(&source[..insert_at]);
+    if insert_at == source.len() && !source.ends_with('\n') && !source.is_empty() {
+        output.push('\n');
+    }
+    output.push_str(&define);
+    output.push_str(&source[insert_at..]);
+    output
+}
+
+// `uniform highp vec4 Name;` becomes `const highp vec4 Name = value;`. The
+// material's uniform table is left alone, the game still uploads a value but
+// the shader never reads it.
+fn pin_uniform(source: &str, name: &str, value: &str) -> String {
+    source
+        .split_inclusive('\n')
+        .map(|line| {
+            let trimmed = line.trim();
+            let Some(declaration) = trimmed.strip_suffix(';') else {
+                return line.to_string();
+            };
+            let tokens: Vec<&str> = declaration.split_whitespace().collect();
+            if tokens.len() < 3 || tokens[0] != "uniform" || tokens[tokens.len() - 1] != name {
+                return line.to_string();
+            }
+            let qualifiers = tokens[1..tokens.len() - 1].join(" ");
+            let ending = if line.ends_with('\n') { "\n" } else { "" };
+            format!("const {qualifiers} {name} = {value};{ending}")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacement(find: &str, replace: &str, mode: ReplaceMode) -> SourceReplacement {
+        SourceReplacement {
+            find: find.to_string(),
+            replace: replace.to_string(),
+            mode,
+            regex: (mode == ReplaceMode::Regex).then(|| Regex::new(find).unwrap()),
+        }
+    }
+
+    #[test]
+    fn replacements_count_their_matches() {
+        let literal = replacement("fog", "mist", ReplaceMode::Literal);
+        assert_eq!(literal.apply("fog = fogColor;\n"), ("mist = mistColor;\n".to_string(), 2));
+
+        let line = replacement("x = 1;", "x = 2;", ReplaceMode::Line);
+        assert_eq!(
+            line.apply("x = 1;\ny = x = 1;\n    x = 1;"),
+            ("x = 2;\ny = x = 1;\nx = 2;".to_string(), 2)
+        );
+
+        let regex = replacement(r"(\w+) \* 2\.0", "$1 + $1", ReplaceMode::Regex);
+        assert_eq!(regex.apply("a * 2.0; b * 2.0;"), ("a + a; b + b;".to_string(), 2));
+        assert_eq!(regex.apply("a * 3.0;"), ("a * 3.0;".to_string(), 0));
+    }
+
+    #[test]
+    fn defines_go_after_the_version() {
+        let source = "#version 300 es\n#define FOG 0\nvoid main() {}\n";
+        assert_eq!(
+            add_define(source, "FOG", "1"),
+            "#version 300 es\n#define FOG 1\nvoid main() {}\n"
+        );
+        assert_eq!(add_define("void main() {}\n", "FOG", "1"), "#define FOG 1\nvoid main() {}\n");
+        assert_eq!(add_define("#version 300 es", "FOG", "1"), "#version 300 es\n#define FOG 1\n");
+    }
+
+    #[test]
+    fn remove_define_keeps_longer_names() {
+        let source = "#define FOG 1\n#define FOG_COLOR 2\n  #define FOG(x) x\nfloat FOG;\n";
+        assert_eq!(remove_define(source, "FOG"), "#define FOG_COLOR 2\nfloat FOG;\n");
+    }
+
+    #[test]
+    fn pinned_uniforms_become_constants() {
+        let source = "uniform vec4 FogColorExtra;\n  uniform highp vec4 FogColor ;";
+        assert_eq!(
+            pin_uniform(source, "FogColor", "vec4(0.0)"),
+            "uniform vec4 FogColorExtra;\nconst highp vec4 FogColor = vec4(0.0);"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use materialbin::{CompiledMaterialDefinition, MinecraftVersion};
+use scroll::Pread;
+
+// Material bins don't say which format they are in, the first format that
+// parses is taken
+pub fn parse(data: &[u8]) -> Option<(CompiledMaterialDefinition, MinecraftVersion)> {
+    for version in materialbin::ALL_VERSIONS {
+        match data.pread_with::<CompiledMaterialDefinition>(0, version) {
+            Ok(material) => return Some((material, version)),
+            Err(e) => log::trace!("[{version}] Parsing failed: {e}"),
+        }
+    }
+    None
+}
+
+pub fn write(material: &CompiledMaterialDefinition, version: MinecraftVersion, size_hint: usize) -> Result<Vec<u8>, String> {
+    let mut output = Vec::with_capacity(size_hint);
+    material
+        .write(&mut output, version)
+        .map_err(|e| format!("Write error: {e}"))?;
+    Ok(output)
+}
+
+pub struct Conversion {
+    pub from: MinecraftVersion,
+    // None when the material already was in the target format and nothing
+    // was stripped, the input can be used as it is then
+    pub output: Option<Vec<u8>>,
+    // Size of the shader blobs `strip` removed
+    pub stripped_bytes: usize,
+}
+
+// Parses, optionally strips shaders and writes for `target`. Output is parsed
+// back before it is returned so a broken conversion never leaves here.
+pub fn convert(
+    data: &[u8],
+    target: MinecraftVersion,
+    // Returns how many shader bytes it removed
+    strip: impl FnOnce(&mut CompiledMaterialDefinition) -> usize,
+) -> Result<Conversion, String> {
+    let (mut material, from) = parse(data).ok_or_else(|| "matches no known material format".to_string())?;
+    let stripped_bytes = strip(&mut material);
+    if from == target && stripped_bytes == 0 {
+        return Ok(Conversion {
+            from,
+            output: None,
+            stripped_bytes,
+        });
+    }
+    let output = write(&material, target, data.len()).map_err(|e| format!("converting from {from} failed: {e}"))?;
+    if let Err(e) = output.pread_with::<CompiledMaterialDefinition>(0, target) {
+        return Err(format!("converted from {from} but does not parse as {target}: {e}"));
+    }
+    Ok(Conversion {
+        from,
+        output: Some(output),
+        stripped_bytes,
+    })
+}
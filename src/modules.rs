@@ -0,0 +1,171 @@
+use crate::elf;
+use std::ffi::CStr;
+
+#[cfg(target_pointer_width = "64")]
+use libc::Elf64_Phdr as Phdr;
+#[cfg(target_pointer_width = "32")]
+use libc::Elf32_Phdr as Phdr;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub start: usize,
+    pub size: usize,
+    pub readable: bool,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadedModule {
+    // Path as the linker sees it, can be something like base.apk!/lib/arm64-v8a/libx.so
+    pub path: String,
+    pub soname: Option<String>,
+    // Where the ELF header is mapped
+    pub base: usize,
+    // What gets added to every vaddr in the file
+    pub bias: usize,
+    pub segments: Vec<Segment>,
+    // Absolute range of .text, when the file could be read from disk
+    pub text: Option<(usize, usize)>,
+}
+
+impl LoadedModule {
+    // Matches on file name first and falls back to DT_SONAME, which survives
+    // launchers renaming the library
+    pub fn matches(&self, name: &str) -> bool {
+        let file_name = self.path.rsplit('/').next().unwrap_or(&self.path);
+        file_name == name || self.soname.as_deref() == Some(name)
+    }
+
+    pub fn executable_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|segment| segment.executable)
+    }
+
+    // The range worth scanning for code, .text when we know it and otherwise
+    // everything between the first and last executable segment
+    pub fn code_range(&self) -> Option<(usize, usize)> {
+        if let Some(text) = self.text {
+            return Some(text);
+        }
+        let start = self.executable_segments().map(|s| s.start).min()?;
+        let end = self.executable_segments().map(|s| s.start + s.size).max()?;
+        Some((start, end - start))
+    }
+}
+
+pub fn loaded_modules() -> Vec<LoadedModule> {
+    let mut modules: Vec<LoadedModule> = Vec::new();
+    unsafe {
+        libc::dl_iterate_phdr(Some(collect_module), (&mut modules as *mut Vec<LoadedModule>).cast());
+    }
+    modules
+}
+
+pub fn find_module(name: &str) -> Option<LoadedModule> {
+    let mut module = loaded_modules().into_iter().find(|module| module.matches(name))?;
+    // Only the plain files can be opened, libraries straight from the apk
+    // have to make do with their segments
+    if !module.path.contains("!/") {
+        module.text = elf::text_section(&module.path)
+            .map(|(vaddr, size)| (module.bias.wrapping_add(vaddr), size));
+    }
+    Some(module)
+}
+
+unsafe extern "C" fn collect_module(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    data: *mut libc::c_void,
+) -> libc::c_int {
+    let modules = &mut *data.cast::<Vec<LoadedModule>>();
+    let info = &*info;
+    let path = if info.dlpi_name.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(info.dlpi_name).to_string_lossy().into_owned()
+    };
+    let bias = info.dlpi_addr as usize;
+    let phdrs: &[Phdr] = if info.dlpi_phdr.is_null() {
+        &[]
+    } else {
+        core::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize)
+    };
+
+    let mut segments = Vec::new();
+    let mut base = None;
+    let mut soname = None;
+    for phdr in phdrs {
+        match phdr.p_type {
+            PT_LOAD => {
+                let start = bias.wrapping_add(phdr.p_vaddr as usize);
+                if phdr.p_offset == 0 {
+                    base = Some(start);
+                }
+                segments.push(Segment {
+                    start,
+                    size: phdr.p_memsz as usize,
+                    readable: phdr.p_flags & PF_R != 0,
+                    writable: phdr.p_flags & PF_W != 0,
+                    executable: phdr.p_flags & PF_X != 0,
+                });
+            }
+            PT_DYNAMIC => {
+                soname = elf::soname(bias, bias.wrapping_add(phdr.p_vaddr as usize));
+            }
+            _ => {}
+        }
+    }
+    modules.push(LoadedModule {
+        path,
+        soname,
+        base: base.unwrap_or(bias),
+        bias,
+        segments,
+        text: None,
+    });
+    0
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::test_fixture;
+
+    #[test]
+    fn finds_a_dlopened_library() {
+        let fixture = test_fixture::load("fixture", "modules");
+        let module = find_module(&fixture.soname).expect("fixture is loaded");
+        assert_eq!(module.path, fixture.path.to_str().unwrap());
+        assert_eq!(module.soname.as_deref(), Some(fixture.soname.as_str()));
+
+        let (start, size) = module.code_range().unwrap();
+        for symbol in ["fixture_open", "fixture_fopen", "fixture_access"] {
+            let addr = fixture.symbol(symbol);
+            assert!((start..start + size).contains(&addr), "{symbol} is outside .text");
+        }
+
+        let (vaddr, text_size) = elf::text_section(&module.path).unwrap();
+        assert_eq!((module.bias + vaddr, text_size), (start, size));
+        let segment = module
+            .executable_segments()
+            .find(|segment| segment.start <= start)
+            .unwrap();
+        assert!(start + size <= segment.start + segment.size);
+    }
+
+    #[test]
+    fn reads_the_build_id_of_a_loaded_library() {
+        let fixture = test_fixture::load("fixture", "modules");
+        let module = find_module(&fixture.soname).unwrap();
+        let id = unsafe { elf::build_id(module.base, module.bias) }.unwrap();
+        // --build-id=sha1
+        assert_eq!(id.len(), 40);
+        assert!(id.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{config::write_atomic, signatures, SimpleMapRange};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, sync::Mutex};
+
+// Scanning all of libminecraftpe for every signature is slow, so resolved
+// addresses get remembered per build. They are stored relative to the library
+// base since the load address changes every launch.
+const CACHE_DIR: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/cache";
+const CACHE_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/cache/offsets.json";
+
+static CACHE: Mutex<Option<OffsetCache>> = Mutex::new(None);
+
+#[derive(Serialize, Deserialize)]
+struct OffsetCache {
+    build_id: String,
+    arch: String,
+    offsets: BTreeMap<String, usize>,
+}
+
+impl OffsetCache {
+    fn load(build_id: &str) -> Self {
+        let arch = std::env::consts::ARCH;
+        let cached = fs::read_to_string(CACHE_FILE)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<OffsetCache>(&contents).ok());
+        match cached {
+            Some(cache) if cache.build_id == build_id && cache.arch == arch => cache,
+            Some(_) => {
+                log::info!("libminecraftpe.so changed, dropping cached offsets");
+                Self::empty(build_id)
+            }
+            None => Self::empty(build_id),
+        }
+    }
+
+    fn empty(build_id: &str) -> Self {
+        Self {
+            build_id: build_id.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            offsets: BTreeMap::new(),
+        }
+    }
+
+    fn save(&self) {
+        if let Err(e) = fs::create_dir_all(CACHE_DIR) {
+            log::warn!("Failed to create cache directory: {e}");
+            return;
+        }
+        let json = match serde_json::to_string_pretty(self) {
+            Ok(json) => json,
+            Err(e) => {
+                log::warn!("Failed to serialize offset cache: {e}");
+                return;
+            }
+        };
+        if let Err(e) = write_atomic(CACHE_FILE, json.as_bytes()) {
+            log::warn!("Failed to write offset cache: {e}");
+        }
+    }
+}
+
+// Resolves a named signature, going through the cache when we know which
+// build of the library we are looking at
+pub fn resolve(name: &str, range: &SimpleMapRange, build_id: Option<&str>) -> Option<*const u8> {
+    let db = signatures::database();
+    let Some(build_id) = build_id else {
+        return db.resolve(name, range, None);
+    };
+    let mut cache = CACHE.lock().unwrap();
+    let cache = cache.get_or_insert_with(|| OffsetCache::load(build_id));
+
+    if let Some(&offset) = cache.offsets.get(name) {
+        let addr = range.base().wrapping_add(offset) as *const u8;
+        if db.verify(name, addr, range, Some(build_id)) {
+            log::info!("Using cached offset {offset:#x} for {name}");
+            return Some(addr);
+        }
+        log::warn!("Cached offset for {name} does not match anymore, scanning");
+        cache.offsets.remove(name);
+    }
+
+    let addr = db.resolve(name, range, Some(build_id));
+    if let Some(addr) = addr {
+        cache.offsets.insert(name.to_string(), addr as usize - range.base());
+    }
+    cache.save();
+    addr
+}
@@ -1,25 +1,176 @@
 use plt_rs::DynamicLibrary;
 use region::{protect_with_handle, Protection};
 
+// One patched GOT slot, enough to put things back the way they were
+#[derive(Debug)]
+pub struct PltSlot {
+    pub symbol: &'static str,
+    pub slot: *mut *const u8,
+    pub original: *const u8,
+    pub replacement: *const u8,
+}
+unsafe impl Send for PltSlot {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolHookState {
+    Patched,
+    // The library does not import it, nothing to patch
+    Missing,
+    Failed,
+}
+
+impl SymbolHookState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SymbolHookState::Patched => "patched",
+            SymbolHookState::Missing => "missing",
+            SymbolHookState::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PltHooks {
+    library: String,
+    slots: Vec<PltSlot>,
+    report: Vec<(&'static str, SymbolHookState)>,
+}
+
+impl PltHooks {
+    pub fn library(&self) -> &str {
+        &self.library
+    }
+
+    // What the slot pointed to before we patched it, null if it wasn't patched
+    pub fn original(&self, symbol: &str) -> *const u8 {
+        self.slots
+            .iter()
+            .find(|slot| slot.symbol == symbol)
+            .map_or(std::ptr::null(), |slot| slot.original)
+    }
+
+    pub fn report(&self) -> &[(&'static str, SymbolHookState)] {
+        &self.report
+    }
+
+    pub fn unpatched(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.report
+            .iter()
+            .filter(|(_, state)| *state != SymbolHookState::Patched)
+            .map(|(symbol, _)| *symbol)
+    }
+
+    // Restores the original targets, slots that someone else changed after us
+    // are left alone so we don't undo their hook
+    pub fn unhook(&mut self) {
+        for slot in self.slots.drain(..) {
+            let current = unsafe { slot.slot.read_unaligned() };
+            if current != slot.replacement {
+                log::warn!("{} was re-hooked by someone else, not restoring it", slot.symbol);
+                continue;
+            }
+            if let Err(e) = write_slot(slot.slot, slot.original) {
+                log::error!("Failed to restore {}: {e}", slot.symbol);
+            }
+        }
+    }
+}
+
 pub fn replace_plt_functions<const LEN: usize>(
     dyn_lib: &DynamicLibrary,
-    functions: [(&str, *const u8); LEN],
-) {
+    functions: [(&'static str, *const u8); LEN],
+) -> PltHooks {
     let base_addr = dyn_lib.library().addr();
+    let mut hooks = PltHooks {
+        library: dyn_lib.library().name().to_string(),
+        slots: Vec::with_capacity(LEN),
+        report: Vec::with_capacity(LEN),
+    };
     for (fn_name, replacement) in functions {
         let Some(fn_plt) = dyn_lib.try_find_function(fn_name) else {
+            log::warn!("{} does not import {fn_name}", hooks.library);
+            hooks.report.push((fn_name, SymbolHookState::Missing));
             continue;
         };
-        replace_plt_function(base_addr, fn_plt.r_offset as usize, replacement);
+        let slot_offset = fn_plt.r_offset as usize;
+        let state = match replace_plt_function(fn_name, base_addr, slot_offset, replacement) {
+            Ok(slot) => {
+                hooks.slots.push(slot);
+                SymbolHookState::Patched
+            }
+            Err(e) => {
+                log::error!("Failed to patch {fn_name} in {}: {e}", hooks.library);
+                SymbolHookState::Failed
+            }
+        };
+        hooks.report.push((fn_name, state));
     }
+    hooks
 }
-fn replace_plt_function(base_addr: usize, offset: usize, replacement: *const u8) {
+fn replace_plt_function(
+    symbol: &'static str,
+    base_addr: usize,
+    offset: usize,
+    replacement: *const u8,
+) -> Result<PltSlot, region::Error> {
     let plt_fn_ptr = (base_addr + offset) as *mut *const u8;
+    let original = unsafe { plt_fn_ptr.read_unaligned() };
+    write_slot(plt_fn_ptr, replacement)?;
+    Ok(PltSlot {
+        symbol,
+        slot: plt_fn_ptr,
+        original,
+        replacement,
+    })
+}
+fn write_slot(plt_fn_ptr: *mut *const u8, value: *const u8) -> Result<(), region::Error> {
     const PTR_LEN: usize = std::mem::size_of::<usize>();
     unsafe {
         // Set the memory page to read, write
-        let _handle = protect_with_handle(plt_fn_ptr, PTR_LEN, Protection::READ_WRITE).unwrap();
+        let _handle = protect_with_handle(plt_fn_ptr, PTR_LEN, Protection::READ_WRITE)?;
         // Replace the function address
-        plt_fn_ptr.write_unaligned(replacement);
+        plt_fn_ptr.write_unaligned(value);
+    }
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::test_fixture;
+
+    unsafe extern "C" fn fake_access(_path: *const libc::c_char, _mode: libc::c_int) -> libc::c_int {
+        42
+    }
+
+    #[test]
+    fn unhook_restores_the_original_slot() {
+        let fixture = test_fixture::load("fixture", "plthook");
+        type AccessFn = unsafe extern "C" fn(*const libc::c_char) -> libc::c_int;
+        let fixture_access: AccessFn = unsafe { std::mem::transmute(fixture.symbol("fixture_access")) };
+        let lib = plt_rs::collect_modules()
+            .into_iter()
+            .find(|lib| lib.name().contains(fixture.soname.as_str()))
+            .unwrap();
+        let dyn_lib = DynamicLibrary::initialize(lib).unwrap();
+
+        let mut hooks = replace_plt_functions(
+            &dyn_lib,
+            [
+                ("access", fake_access as *const u8),
+                ("not_imported", fake_access as *const u8),
+            ],
+        );
+        assert_eq!(
+            hooks.report(),
+            [("access", SymbolHookState::Patched), ("not_imported", SymbolHookState::Missing)]
+        );
+        assert!(!hooks.original("access").is_null());
+        assert_eq!(unsafe { fixture_access(c"/".as_ptr()) }, 42);
+
+        hooks.unhook();
+        assert!(hooks.original("access").is_null());
+        assert_eq!(unsafe { fixture_access(c"/".as_ptr()) }, 0);
+        assert_eq!(unsafe { fixture_access(c"/does/not/exist".as_ptr()) }, -1);
     }
 }
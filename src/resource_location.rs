@@ -0,0 +1,108 @@
+use libc::c_void;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// ResourceFileSystem from the game, tells the pack manager where a path lives
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+#[repr(i32)]
+pub enum ResourceFileSystem {
+    // Resource and behavior packs, what we always used before
+    #[default]
+    UserPackage = 0,
+    // Files inside the apk
+    AppPackage = 1,
+    // Absolute paths, as is
+    Raw = 2,
+    RawPersistent = 3,
+    SettingsDir = 4,
+    ExternalDir = 5,
+    ServerPackage = 6,
+    DataDir = 7,
+    UserDir = 8,
+    ScreenshotsDir = 9,
+    StoreCache = 10,
+}
+
+// Owned by the C++ side, the game takes these by reference so the layout
+// has to match exactly
+#[repr(transparent)]
+pub struct ResourceLocation(*mut c_void);
+
+impl ResourceLocation {
+    pub fn builder(path: &[u8]) -> ResourceLocationBuilder<'_> {
+        ResourceLocationBuilder {
+            path,
+            file_system: ResourceFileSystem::default(),
+        }
+    }
+}
+
+impl Drop for ResourceLocation {
+    fn drop(&mut self) {
+        unsafe { resource_location_free(self.0) }
+    }
+}
+
+pub struct ResourceLocationBuilder<'a> {
+    path: &'a [u8],
+    file_system: ResourceFileSystem,
+}
+
+impl ResourceLocationBuilder<'_> {
+    pub fn file_system(mut self, file_system: ResourceFileSystem) -> Self {
+        self.file_system = file_system;
+        self
+    }
+
+    pub fn build(self) -> ResourceLocation {
+        let path_hash = hash_path(self.path);
+        // std::hash<int> is the identity in libc++
+        let full_hash = path_hash ^ self.file_system as i32 as u64;
+        unsafe {
+            resource_location_init(
+                self.file_system as i32,
+                self.path.as_ptr().cast(),
+                self.path.len(),
+                path_hash,
+                full_hash,
+            )
+        }
+    }
+}
+
+// HashedString::computeHash as public reimplementations of it have it:
+// FNV-1 (multiply, then xor) over the chars, with 0 for an empty string.
+// Not the FNV-1a in crate::hash, and chars widen the way the platform's char
+// does.
+pub fn hash_path(path: &[u8]) -> u64 {
+    if path.is_empty() {
+        return 0;
+    }
+    path.iter().fold(0xcbf29ce484222325, |hash: u64, &byte| {
+        hash.wrapping_mul(0x100000001b3) ^ byte as libc::c_char as u64
+    })
+}
+
+extern "C" {
+    fn resource_location_init(
+        file_system: i32,
+        strptr: *const libc::c_char,
+        size: libc::size_t,
+        path_hash: u64,
+        full_hash: u64,
+    ) -> ResourceLocation;
+    fn resource_location_free(loc: *mut c_void);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_hash_with_fnv1() {
+        assert_eq!(hash_path(b""), 0);
+        assert_eq!(hash_path(b"a"), 0xaf63bd4c8601b7be);
+        assert_eq!(hash_path(b"foobar"), 0x340d8765a4dda9c2);
+    }
+}
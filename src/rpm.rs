@@ -0,0 +1,129 @@
+use crate::{
+    config::{get_config, RpmSelection},
+    signatures, ResourceFileSystem, ResourceLocation,
+};
+use core::mem::transmute;
+use cxx::CxxString;
+use libc::c_void;
+use std::{ffi::CStr, mem::size_of, pin::Pin, sync::Mutex};
+
+// The game builds more than one ResourcePackManager (client, world and a few
+// others), every one of them gets tracked here and the config decides which
+// one serves our redirects
+pub type RpmLoadFn = unsafe extern "C" fn(*mut c_void, ResourceLocation, Pin<&mut CxxString>) -> bool;
+
+const DEFAULT_LOAD_SLOT: usize = 2;
+
+#[derive(Clone, Copy)]
+pub struct RpmInstance {
+    pub ptr: *mut c_void,
+    pub load: RpmLoadFn,
+    // What the vtable pointer was right after construction
+    vtable: usize,
+}
+unsafe impl Send for RpmInstance {}
+
+impl RpmInstance {
+    // Destructors point the object at their base class vtable as they run
+    // and freed memory gets reused, so a manager whose vtable pointer changed
+    // is gone. The read goes through the kernel so an unmapped page is an
+    // error instead of a crash.
+    fn is_alive(&self) -> bool {
+        read_word(self.ptr as usize) == Some(self.vtable)
+    }
+}
+
+fn read_word(addr: usize) -> Option<usize> {
+    let mut word = 0usize;
+    let local = libc::iovec {
+        iov_base: (&mut word as *mut usize).cast(),
+        iov_len: size_of::<usize>(),
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut c_void,
+        iov_len: size_of::<usize>(),
+    };
+    let read = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    (read == size_of::<usize>() as isize).then_some(word)
+}
+
+static INSTANCES: Mutex<Vec<RpmInstance>> = Mutex::new(Vec::new());
+
+pub fn register(ptr: *mut c_void) {
+    let instance = RpmInstance {
+        ptr,
+        load: unsafe { get_load(ptr) },
+        vtable: unsafe { *(ptr as *const usize) },
+    };
+    let mut instances = INSTANCES.lock().unwrap();
+    // A new manager can land where a destroyed one used to be
+    if let Some(existing) = instances.iter_mut().find(|rpm| rpm.ptr == ptr) {
+        *existing = instance;
+        log::info!("ResourcePackManager at {ptr:p} was constructed again");
+        return;
+    }
+    instances.push(instance);
+    log::info!("ResourcePackManager #{} at {ptr:p}", instances.len() - 1);
+}
+
+// Managers to try, in order, according to the selection policy. Destroyed
+// managers are dropped first so Last and Index only count live ones, there
+// is no destructor hook so a manager dying right after this is still possible.
+pub fn candidates() -> Vec<RpmInstance> {
+    let mut instances = INSTANCES.lock().unwrap();
+    instances.retain(|rpm| {
+        let alive = rpm.is_alive();
+        if !alive {
+            log::info!("ResourcePackManager at {:p} was destroyed", rpm.ptr);
+        }
+        alive
+    });
+    match get_config().rpm_selection {
+        RpmSelection::First => instances.first().copied().into_iter().collect(),
+        RpmSelection::Last => instances.last().copied().into_iter().collect(),
+        RpmSelection::Index(index) => instances.get(index).copied().into_iter().collect(),
+        RpmSelection::FirstMatch => instances.clone(),
+    }
+}
+
+// Typed access to a game ResourcePackManager, vtable slots come from the
+// signature database so they can be fixed without a rebuild. Only the load
+// slot is mapped so far. Listing the pack stacks, an exists check and loading
+// from a single pack need the stack layout and more slots, none of which have
+// been mapped against a shipped build, so they are left out instead of
+// guessed at.
+#[derive(Clone, Copy)]
+pub struct ResourcePackManager {
+    instance: RpmInstance,
+}
+
+impl ResourcePackManager {
+    pub fn selected() -> Vec<ResourcePackManager> {
+        candidates()
+            .into_iter()
+            .filter(|instance| !instance.ptr.is_null())
+            .map(|instance| ResourcePackManager { instance })
+            .collect()
+    }
+
+    // Loads a file from the whole pack stack, None when no pack has it
+    pub fn load_from(&self, file_system: ResourceFileSystem, path: &CStr) -> Option<Vec<u8>> {
+        cxx::let_cxx_string!(out = "");
+        let location = ResourceLocation::builder(path.to_bytes())
+            .file_system(file_system)
+            .build();
+        unsafe { (self.instance.load)(self.instance.ptr, location, out.as_mut()) };
+        if out.is_empty() {
+            return None;
+        }
+        Some(out.as_bytes().to_vec())
+    }
+}
+
+unsafe fn get_load(packm_ptr: *mut c_void) -> RpmLoadFn {
+    let slot = signatures::database()
+        .vtable_slot(signatures::RPM_LOAD_SLOT, crate::build_id())
+        .unwrap_or(DEFAULT_LOAD_SLOT);
+    let vptr = *transmute::<*mut c_void, *mut *mut *const u8>(packm_ptr);
+    transmute::<*const u8, RpmLoadFn>(*vptr.add(slot))
+}
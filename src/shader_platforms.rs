@@ -0,0 +1,82 @@
+use crate::config::get_config;
+use materialbin::CompiledMaterialDefinition;
+use std::sync::OnceLock;
+
+// Materials carry shaders for every backend Mojang ships, Android only ever
+// runs the ESSL ones and only up to what the GPU supports. Dropping the rest
+// saves memory and parsing time on the game side. This only runs on
+// materials that get converted anyway, vanilla ones are served untouched.
+
+static GLES_VERSION: OnceLock<Option<u32>> = OnceLock::new();
+
+// ro.opengles.version packs major in the high 16 bits and minor in the low ones
+fn gles_version() -> Option<u32> {
+    *GLES_VERSION.get_or_init(|| {
+        let version = read_gles_property();
+        match version {
+            Some(version) => log::info!("OpenGL ES {}.{} detected", version >> 16, version & 0xffff),
+            None => log::warn!("Could not detect the OpenGL ES version, keeping every ESSL shader"),
+        }
+        version
+    })
+}
+
+#[cfg(target_os = "android")]
+fn read_gles_property() -> Option<u32> {
+    let mut value = [0 as libc::c_char; libc::PROP_VALUE_MAX as usize];
+    let len = unsafe { libc::__system_property_get(c"ro.opengles.version".as_ptr(), value.as_mut_ptr()) };
+    if len <= 0 {
+        return None;
+    }
+    let value = unsafe { std::ffi::CStr::from_ptr(value.as_ptr()) };
+    value.to_str().ok()?.trim().parse().ok()
+}
+
+#[cfg(not(target_os = "android"))]
+fn read_gles_property() -> Option<u32> {
+    None
+}
+
+// ESSL_100 needs ES 2.0, ESSL_300 3.0 and ESSL_310 3.1
+fn keeps(platform: &str) -> bool {
+    let required = match platform {
+        "ESSL_100" => 0x20000,
+        "ESSL_300" => 0x30000,
+        "ESSL_310" => 0x30001,
+        _ => return false,
+    };
+    !gles_version().is_some_and(|version| version < required)
+}
+
+pub fn enabled() -> bool {
+    get_config().strip_shader_platforms
+}
+
+// Part of the material cache key, so a GPU or setting change converts again
+pub fn cache_tag() -> String {
+    if !enabled() {
+        return "all".to_string();
+    }
+    match gles_version() {
+        Some(version) => format!("essl{version:x}"),
+        None => "essl".to_string(),
+    }
+}
+
+// Removes the shaders Android will never use, returns the size of the shader
+// blobs that were dropped
+pub fn strip(material: &mut CompiledMaterialDefinition) -> usize {
+    let mut removed = 0;
+    for pass in material.passes.values_mut() {
+        for variant in pass.variants.iter_mut() {
+            variant.shader_codes.retain(|stage, code| {
+                let keep = keeps(&stage.platform_name);
+                if !keep {
+                    removed += code.bgfx_shader_data.len();
+                }
+                keep
+            });
+        }
+    }
+    removed
+}
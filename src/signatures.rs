@@ -0,0 +1,223 @@
+use crate::SimpleMapRange;
+use serde::Deserialize;
+use std::{fs, sync::OnceLock};
+use tinypatscan::Pattern;
+
+// Signatures ship with the mod but can be overridden without a new release,
+// entries from the override file are always tried first
+const EMBEDDED_SIGNATURES: &str = include_str!("data/signatures.json");
+const SIGNATURES_OVERRIDE_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/signatures.json";
+
+const MAX_PATTERN_LEN: usize = 80;
+
+pub const RPM_CTOR: &str = "ResourcePackManager::ResourcePackManager";
+pub const RPM_LOAD_SLOT: &str = "ResourcePackManager::load";
+
+static DATABASE: OnceLock<SignatureDb> = OnceLock::new();
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SignatureEntry {
+    pub name: String,
+    pub arch: String,
+    // Hex encoded NT_GNU_BUILD_ID, entries without one apply to any build
+    #[serde(default)]
+    pub build_id: Option<String>,
+    pub pattern: String,
+    // Added to the match address, for patterns that don't start at the function
+    #[serde(default)]
+    pub offset: isize,
+    #[serde(default)]
+    pub thumb: bool,
+}
+
+// Index into a class vtable, these rarely move between versions so arch and
+// build-id are optional
+#[derive(Deserialize, Debug, Clone)]
+pub struct VtableSlotEntry {
+    pub name: String,
+    #[serde(default)]
+    pub arch: Option<String>,
+    #[serde(default)]
+    pub build_id: Option<String>,
+    pub index: usize,
+}
+
+#[derive(Deserialize)]
+struct SignatureFile {
+    signatures: Vec<SignatureEntry>,
+    #[serde(default)]
+    vtable_slots: Vec<VtableSlotEntry>,
+}
+
+pub struct SignatureDb {
+    entries: Vec<SignatureEntry>,
+    vtable_slots: Vec<VtableSlotEntry>,
+}
+
+pub fn database() -> &'static SignatureDb {
+    DATABASE.get_or_init(SignatureDb::load)
+}
+
+impl SignatureDb {
+    fn load() -> Self {
+        let mut entries = Vec::new();
+        let mut vtable_slots = Vec::new();
+        match fs::read_to_string(SIGNATURES_OVERRIDE_FILE) {
+            Ok(contents) => match serde_json::from_str::<SignatureFile>(&contents) {
+                Ok(file) => {
+                    log::info!(
+                        "Loaded {} signatures from {}",
+                        file.signatures.len(),
+                        SIGNATURES_OVERRIDE_FILE
+                    );
+                    entries.extend(file.signatures);
+                    vtable_slots.extend(file.vtable_slots);
+                }
+                Err(e) => log::error!("Ignoring broken {}: {}", SIGNATURES_OVERRIDE_FILE, e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Cannot read {}: {}", SIGNATURES_OVERRIDE_FILE, e),
+        }
+        match serde_json::from_str::<SignatureFile>(EMBEDDED_SIGNATURES) {
+            Ok(file) => {
+                entries.extend(file.signatures);
+                vtable_slots.extend(file.vtable_slots);
+            }
+            Err(e) => log::error!("Embedded signature database is broken: {}", e),
+        }
+        let arch = std::env::consts::ARCH;
+        entries.retain(|entry| entry.arch == arch);
+        vtable_slots.retain(|slot| slot.arch.is_none() || slot.arch.as_deref() == Some(arch));
+        Self {
+            entries,
+            vtable_slots,
+        }
+    }
+
+    // Build specific slots win over generic ones, the override file over the
+    // embedded one
+    pub fn vtable_slot(&self, name: &str, build_id: Option<&str>) -> Option<usize> {
+        let named = || self.vtable_slots.iter().filter(|slot| slot.name == name);
+        let exact = named().find(|slot| {
+            build_id.is_some_and(|id| slot.build_id.as_deref().is_some_and(|e| e.eq_ignore_ascii_case(id)))
+        });
+        exact
+            .or_else(|| named().find(|slot| slot.build_id.is_none()))
+            .map(|slot| slot.index)
+    }
+
+    // Entries for this exact build come first, the generic ones after
+    fn candidates<'a>(
+        &'a self,
+        name: &'a str,
+        build_id: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a SignatureEntry> {
+        let named = move |entry: &&SignatureEntry| entry.name == name;
+        let exact = self.entries.iter().filter(named).filter(move |entry| {
+            build_id.is_some_and(|id| entry.build_id.as_deref().is_some_and(|e| e.eq_ignore_ascii_case(id)))
+        });
+        let generic = self
+            .entries
+            .iter()
+            .filter(named)
+            .filter(|entry| entry.build_id.is_none());
+        exact.chain(generic)
+    }
+
+    pub fn resolve(
+        &self,
+        name: &str,
+        range: &SimpleMapRange,
+        build_id: Option<&str>,
+    ) -> Option<*const u8> {
+        let libbytes =
+            unsafe { core::slice::from_raw_parts(range.start() as *const u8, range.size()) };
+        let mut tried = 0;
+        for entry in self.candidates(name, build_id) {
+            tried += 1;
+            let sig = match parse_pattern(&entry.pattern) {
+                Ok(sig) => sig,
+                Err(e) => {
+                    log::error!("Bad pattern for {}: {}", entry.name, e);
+                    continue;
+                }
+            };
+            let found = if cfg!(target_arch = "arm") {
+                sig.search(libbytes)
+            } else {
+                sig.simd_search(libbytes)
+            };
+            let Some(offset) = found else {
+                log::warn!("Signature for {} did not match", entry.name);
+                continue;
+            };
+            let addr = libbytes[offset..].as_ptr().wrapping_offset(entry.offset);
+            let addr = if entry.thumb { addr.wrapping_add(1) } else { addr };
+            log::info!("Resolved {} at {:p}", entry.name, addr);
+            return Some(addr);
+        }
+        if tried == 0 {
+            log::error!("No signatures for {} on {}", name, std::env::consts::ARCH);
+        }
+        None
+    }
+
+    // Checks that one of the patterns for `name` still matches at `addr`, used
+    // to trust a cached address without scanning the whole library again
+    pub fn verify(
+        &self,
+        name: &str,
+        addr: *const u8,
+        range: &SimpleMapRange,
+        build_id: Option<&str>,
+    ) -> bool {
+        self.candidates(name, build_id).any(|entry| {
+            let Ok(sig) = parse_pattern(&entry.pattern) else {
+                return false;
+            };
+            let len = entry.pattern.split_whitespace().count();
+            let addr = if entry.thumb { addr.wrapping_sub(1) } else { addr };
+            let start = addr.wrapping_offset(-entry.offset) as usize;
+            if !range.contains(start) || !range.contains(start + len - 1) {
+                return false;
+            }
+            let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+            sig.search(bytes) == Some(0)
+        })
+    }
+}
+
+// Pattern::from_str panics on garbage, and a panic here takes the game with it
+fn parse_pattern(pattern: &str) -> Result<Pattern<MAX_PATTERN_LEN>, String> {
+    let mut len = 0;
+    for token in pattern.split_whitespace() {
+        let valid = matches!(token, "?" | "??")
+            || (token.len() == 2 && token.bytes().all(|b| b.is_ascii_hexdigit()));
+        if !valid {
+            return Err(format!("invalid token \"{token}\""));
+        }
+        len += 1;
+    }
+    if len == 0 {
+        return Err("pattern is empty".to_string());
+    }
+    if len > MAX_PATTERN_LEN {
+        return Err(format!("pattern is longer than {MAX_PATTERN_LEN} bytes"));
+    }
+    Ok(Pattern::from_str(pattern))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_are_checked_before_parsing() {
+        assert!(parse_pattern("48 8b ? ?? 05 ff").is_ok());
+        assert_eq!(parse_pattern("").err().as_deref(), Some("pattern is empty"));
+        assert_eq!(parse_pattern("48 zz").err().as_deref(), Some("invalid token \"zz\""));
+        assert_eq!(parse_pattern("488b").err().as_deref(), Some("invalid token \"488b\""));
+        let too_long = ["00"; MAX_PATTERN_LEN + 1].join(" ");
+        assert!(parse_pattern(&too_long).is_err());
+    }
+}
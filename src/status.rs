@@ -0,0 +1,237 @@
+use crate::{config::write_atomic, game_version::GameVersion, inline_hooks::HookState, plthook::PltHooks};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    thread::{self, Thread},
+    time::Duration,
+};
+
+// Status file the launcher can read to tell users what actually works on
+// their game version. Changes are written out by a background thread shortly
+// after they happen, some of them come from AAssetManager_open and that
+// can't wait on the disk.
+const STATUS_FILE: &str = "/storage/emulated/0/Android/data/com.origin.launcher/files/origin_mods/status.json";
+
+// Bursts of updates, like a pack full of failing materials, end up as one write
+const FLUSH_DELAY: Duration = Duration::from_millis(500);
+
+pub const PACK_REDIRECTS: &str = "pack_redirects";
+pub const ASSET_HOOKS: &str = "asset_hooks";
+
+static DIRTY: AtomicBool = AtomicBool::new(false);
+static FLUSHER: OnceLock<Option<Thread>> = OnceLock::new();
+
+static STATUS: Mutex<StatusReport> = Mutex::new(StatusReport {
+    mod_version: env!("CARGO_PKG_VERSION"),
+    game_version: None,
+    game_version_source: None,
+    material_format: None,
+    degraded: false,
+    features: BTreeMap::new(),
+    hooks: BTreeMap::new(),
+    inline_hooks: BTreeMap::new(),
+    material_patches: BTreeMap::new(),
+    shader_bytes_stripped: 0,
+    material_failures: BTreeMap::new(),
+});
+
+#[derive(Serialize)]
+struct StatusReport {
+    mod_version: &'static str,
+    game_version: Option<String>,
+    // Where game_version was read from
+    game_version_source: Option<&'static str>,
+    material_format: Option<String>,
+    degraded: bool,
+    features: BTreeMap<&'static str, FeatureStatus>,
+    // library -> symbol -> patched/missing/failed
+    hooks: BTreeMap<String, BTreeMap<&'static str, &'static str>>,
+    inline_hooks: BTreeMap<&'static str, InlineHookStatus>,
+    material_patches: BTreeMap<String, MaterialPatchStatus>,
+    // Size of the shader blobs strip_shader_platforms dropped, counted when a
+    // material is converted and not again when it comes from the cache
+    shader_bytes_stripped: u64,
+    // Replacement materials that got the vanilla file served instead
+    material_failures: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct InlineHookStatus {
+    state: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+// How often a patch file applied or failed since launch
+#[derive(Serialize, Default)]
+struct MaterialPatchStatus {
+    applied: u32,
+    failed: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FeatureStatus {
+    available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+pub fn set_available(feature: &'static str) {
+    update(feature, FeatureStatus {
+        available: true,
+        reason: None,
+    });
+}
+
+// Marks a feature as unavailable, the mod keeps running in a degraded state
+pub fn set_unavailable(feature: &'static str, reason: impl Into<String>) {
+    update(feature, FeatureStatus {
+        available: false,
+        reason: Some(reason.into()),
+    });
+}
+
+pub fn set_hooks(hooks: &PltHooks) {
+    let mut report = STATUS.lock().unwrap();
+    let symbols = hooks
+        .report()
+        .iter()
+        .map(|(symbol, state)| (*symbol, state.as_str()));
+    report
+        .hooks
+        .entry(hooks.library().to_string())
+        .or_default()
+        .extend(symbols);
+    drop(report);
+    mark_dirty();
+}
+
+pub fn set_inline_hook(name: &'static str, state: &HookState) {
+    let status = match state {
+        HookState::Installed(addr) => InlineHookStatus {
+            state: "installed",
+            address: Some(format!("{addr:#x}")),
+            reason: None,
+        },
+        HookState::Disabled => InlineHookStatus {
+            state: "disabled",
+            address: None,
+            reason: None,
+        },
+        HookState::Failed(reason) => InlineHookStatus {
+            state: "failed",
+            address: None,
+            reason: Some(reason.clone()),
+        },
+    };
+    let mut report = STATUS.lock().unwrap();
+    report.inline_hooks.insert(name, status);
+    update_degraded(&mut report);
+    drop(report);
+    mark_dirty();
+}
+
+pub fn set_material_patch(name: &str, failure: Option<String>) {
+    let mut report = STATUS.lock().unwrap();
+    let status = report.material_patches.entry(name.to_string()).or_default();
+    match failure {
+        Some(reason) => {
+            status.failed += 1;
+            status.last_error = Some(reason);
+        }
+        None => status.applied += 1,
+    }
+    drop(report);
+    mark_dirty();
+}
+
+pub fn set_material_failure(path: &str, reason: String) {
+    let mut report = STATUS.lock().unwrap();
+    if report.material_failures.get(path) == Some(&reason) {
+        return;
+    }
+    report.material_failures.insert(path.to_string(), reason);
+    drop(report);
+    mark_dirty();
+}
+
+pub fn set_game_version(version: &GameVersion) {
+    let mut report = STATUS.lock().unwrap();
+    report.game_version = version.semver.map(|semver| semver.to_string());
+    report.game_version_source = version.semver.is_some().then_some(version.source);
+    report.material_format = version.material.map(|material| material.to_string());
+    drop(report);
+    mark_dirty();
+}
+
+pub fn add_shader_bytes_stripped(bytes: u64) {
+    let mut report = STATUS.lock().unwrap();
+    report.shader_bytes_stripped += bytes;
+    drop(report);
+    mark_dirty();
+}
+
+fn update_degraded(report: &mut StatusReport) {
+    report.degraded = report.features.values().any(|f| !f.available)
+        || report.inline_hooks.values().any(|h| h.state == "failed");
+}
+
+fn update(feature: &'static str, status: FeatureStatus) {
+    let mut report = STATUS.lock().unwrap();
+    report.features.insert(feature, status);
+    update_degraded(&mut report);
+    drop(report);
+    mark_dirty();
+}
+
+// Must not be called with STATUS held, without a writer thread the write
+// happens right here
+fn mark_dirty() {
+    DIRTY.store(true, Ordering::Release);
+    let flusher = FLUSHER.get_or_init(|| {
+        match thread::Builder::new().name("xelo-status".to_string()).spawn(flush_loop) {
+            Ok(handle) => Some(handle.thread().clone()),
+            Err(e) => {
+                log::warn!("Failed to start the status writer, writing inline: {e}");
+                None
+            }
+        }
+    });
+    match flusher {
+        Some(thread) => thread.unpark(),
+        None => flush(),
+    }
+}
+
+fn flush_loop() {
+    loop {
+        thread::park();
+        thread::sleep(FLUSH_DELAY);
+        flush();
+    }
+}
+
+fn flush() {
+    if !DIRTY.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    // Only the serialization holds the lock, the disk work happens without it
+    let json = match serde_json::to_string_pretty(&*STATUS.lock().unwrap()) {
+        Ok(json) => json,
+        Err(e) => {
+            log::error!("Failed to serialize status: {e}");
+            return;
+        }
+    };
+    if let Err(e) = write_atomic(STATUS_FILE, json.as_bytes()) {
+        log::warn!("Failed to write status file: {e}");
+    }
+}
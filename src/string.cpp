@@ -13,10 +13,14 @@ struct ResourceLocation {
     ResourceLocation(const std::string& path) : mPath(path) {}
 };
 extern "C" {
-  ResourceLocation* resource_location_init(const char* strptr, size_t size) {
+  // Hashes are computed on the rust side, see resource_location.rs
+  ResourceLocation* resource_location_init(int32_t fileSystem, const char* strptr, size_t size,
+                                           uint64_t pathHash, uint64_t fullHash) {
     ResourceLocation* loc = new ResourceLocation;
-    std::string rust_str(strptr, size);
-    loc->mPath.assign(rust_str);
+    loc->mFileSystem = fileSystem;
+    loc->mPath.assign(strptr, size);
+    loc->mPathHash = pathHash;
+    loc->mFullHash = fullHash;
     return loc;
   }
   void resource_location_free(ResourceLocation* loc) {
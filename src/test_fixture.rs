@@ -0,0 +1,60 @@
+// Builds the C libraries under tests/fixtures with the system compiler and
+// dlopens them, for tests that need a real loaded ELF
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+pub struct Fixture {
+    pub path: PathBuf,
+    pub soname: String,
+    handle: usize,
+}
+
+impl Fixture {
+    pub fn symbol(&self, name: &str) -> usize {
+        let name = CString::new(name).unwrap();
+        let addr = unsafe { libc::dlsym(self.handle as *mut libc::c_void, name.as_ptr()) };
+        assert!(!addr.is_null(), "{} has no {name:?}", self.soname);
+        addr as usize
+    }
+}
+
+// Libraries stay loaded for the rest of the test run
+static LOADED: Mutex<Option<HashMap<&'static str, &'static Fixture>>> = Mutex::new(None);
+
+// Builds tests/fixtures/<source>.c as libxelo_<name>.so, tests that patch a
+// library get their own copy so they can run in parallel
+pub fn load(source: &str, name: &'static str) -> &'static Fixture {
+    let mut loaded = LOADED.lock().unwrap();
+    let loaded = loaded.get_or_insert_with(HashMap::new);
+    if let Some(fixture) = loaded.get(name) {
+        return fixture;
+    }
+    let source = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/{source}.c"));
+    let soname = format!("libxelo_{name}.so");
+    let path = Path::new(env!("OUT_DIR")).join(&soname);
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(compiler)
+        .args(["-shared", "-fPIC", "-O0", "-U_FORTIFY_SOURCE", "-Wl,--build-id=sha1"])
+        .arg(format!("-Wl,-soname,{soname}"))
+        .arg("-o")
+        .arg(&path)
+        .arg(&source)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to build {}", source.display());
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW) };
+    assert!(!handle.is_null(), "failed to dlopen {}", path.display());
+    let fixture = Box::leak(Box::new(Fixture {
+        path,
+        soname,
+        handle: handle as usize,
+    }));
+    loaded.insert(name, fixture);
+    fixture
+}
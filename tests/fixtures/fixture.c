@@ -0,0 +1,32 @@
+// Loaded by the unit tests that need a real shared library: module lookup,
+// ELF parsing and PLT hooking all run against this.
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/stat.h>
+#include <unistd.h>
+
+int fixture_open(const char *path) {
+    return open(path, O_RDONLY);
+}
+
+int fixture_openat(const char *path) {
+    return openat(AT_FDCWD, path, O_RDONLY);
+}
+
+FILE *fixture_fopen(const char *path) {
+    return fopen(path, "r");
+}
+
+int fixture_stat(const char *path) {
+    struct stat st;
+    return stat(path, &st);
+}
+
+int fixture_lstat(const char *path) {
+    struct stat st;
+    return lstat(path, &st);
+}
+
+int fixture_access(const char *path) {
+    return access(path, F_OK);
+}